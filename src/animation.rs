@@ -0,0 +1,386 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use nannou::prelude::*;
+
+use crate::{
+    export::ExportError,
+    geometry::Geometry,
+    mesh::Mesh,
+    raster::{Raster, RgbaImage},
+    stroke::StrokeStyle,
+};
+
+/// Speed of the GIF color quantizer, from 1 (best) to 30 (fastest).
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    pub const ALL: [AnimationFormat; 2] = [AnimationFormat::Gif, AnimationFormat::Apng];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "GIF",
+            AnimationFormat::Apng => "APNG",
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+        }
+    }
+}
+
+/// What changes from one frame to the next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Growth {
+    /// One frame per level.
+    Levels,
+    /// The last level drawn segment by segment over a number of frames.
+    Timed,
+}
+
+impl Growth {
+    pub const ALL: [Growth; 2] = [Growth::Levels, Growth::Timed];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Growth::Levels => "Levels",
+            Growth::Timed => "Timed Growth",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationSettings {
+    pub format: AnimationFormat,
+    pub growth: Growth,
+    pub first_level: usize,
+    pub last_level: usize,
+    /// Frame count of a timed growth.
+    pub frames: usize,
+    /// How long each frame is shown, in milliseconds.
+    pub frame_delay: u32,
+    pub looping: bool,
+    pub width: u32,
+    pub height: u32,
+    /// Anti-aliasing samples per pixel along each axis.
+    pub samples: u32,
+    /// Space kept around the drawing, in pixels.
+    pub margin: f32,
+    pub background: Hsv,
+}
+
+impl AnimationSettings {
+    pub fn default() -> Self {
+        AnimationSettings {
+            format: AnimationFormat::Gif,
+            growth: Growth::Levels,
+            first_level: 1,
+            last_level: 6,
+            frames: 60,
+            frame_delay: 500,
+            looping: true,
+            width: 640,
+            height: 480,
+            samples: 2,
+            margin: 20.0,
+            background: Hsv::new(0.0, 0.0, 0.0),
+        }
+    }
+    /// Number of frames `growth_frames` builds.
+    pub fn frame_count(&self) -> usize {
+        match self.growth {
+            Growth::Levels => (self.first_level..=self.last_level).count(),
+            Growth::Timed => self.frames.max(1),
+        }
+    }
+}
+
+/// The geometry of every frame, with `geometry_at` building the drawing for a level.
+pub fn growth_frames(
+    settings: &AnimationSettings,
+    geometry_at: impl Fn(usize) -> Geometry,
+) -> Vec<Geometry> {
+    match settings.growth {
+        Growth::Levels => (settings.first_level..=settings.last_level)
+            .map(geometry_at)
+            .collect(),
+        Growth::Timed => {
+            let geometry = geometry_at(settings.last_level);
+            let frames = settings.frames.max(1);
+            (1..=frames)
+                .map(|frame| geometry.grown(frame as f32 / frames as f32))
+                .collect()
+        }
+    }
+}
+
+/// One transform into pixel space shared by every frame, so the drawing doesn't jump
+/// around as it changes and the union of `bounds` fits inside the margin. Stroke widths
+/// stay in pixels.
+pub fn frame_transform(
+    bounds: impl IntoIterator<Item = Rect>,
+    width: u32,
+    height: u32,
+    margin: f32,
+) -> impl Fn(Vec2) -> Vec2 {
+    let bounds = bounds.into_iter().reduce(|a, b| {
+        Rect::from_corners(
+            vec2(a.left().min(b.left()), a.bottom().min(b.bottom())),
+            vec2(a.right().max(b.right()), a.top().max(b.top())),
+        )
+    });
+    let room = vec2(width as f32, height as f32) - Vec2::splat(2.0 * margin);
+    let (center, scale) = match bounds {
+        Some(bounds) => {
+            let scale = match (bounds.w() > 0.0, bounds.h() > 0.0) {
+                (true, true) => (room.x / bounds.w()).min(room.y / bounds.h()),
+                (true, false) => room.x / bounds.w(),
+                (false, true) => room.y / bounds.h(),
+                (false, false) => 1.0,
+            };
+            (bounds.xy(), scale.max(0.0))
+        }
+        None => (Vec2::ZERO, 1.0),
+    };
+    move |p| (p - center) * scale
+}
+
+/// Renders `frames` without a window and writes them to `path` as one animation.
+/// `progress` is called with the number of frames rendered so far.
+pub fn export_animation(
+    settings: &AnimationSettings,
+    mut frames: Vec<Geometry>,
+    stroke: &StrokeStyle,
+    path: impl AsRef<Path>,
+    mut progress: impl FnMut(usize),
+) -> Result<String, ExportError> {
+    let path = path.as_ref();
+    if frames.is_empty() {
+        return Err(ExportError::Encode("no frames to animate".to_string()));
+    }
+    let bounds: Vec<Rect> = frames.iter().filter_map(Geometry::bounds).collect();
+    let transform = frame_transform(bounds, settings.width, settings.height, settings.margin);
+    for frame in &mut frames {
+        frame.map_points(&transform);
+    }
+    let raster = Raster::new(
+        settings.width,
+        settings.height,
+        Rect::from_w_h(settings.width as f32, settings.height as f32),
+    )
+    .with_samples(settings.samples)
+    .with_background(settings.background);
+    let images = frames.iter().enumerate().map(|(i, frame)| {
+        let image = raster.render(&Mesh::from_geometry(frame, stroke));
+        progress(i + 1);
+        image
+    });
+
+    let file = File::create(path).map_err(|e| ExportError::Io(e.to_string()))?;
+    let file = BufWriter::new(file);
+    match settings.format {
+        AnimationFormat::Gif => write_gif(settings, images, file),
+        AnimationFormat::Apng => write_apng(settings, frames.len(), images, file),
+    }
+    .map_err(ExportError::Encode)?;
+
+    Ok(format!(
+        "wrote {}: {} frames at {}x{}",
+        path.display(),
+        frames.len(),
+        settings.width,
+        settings.height
+    ))
+}
+
+fn write_gif(
+    settings: &AnimationSettings,
+    images: impl Iterator<Item = RgbaImage>,
+    file: BufWriter<File>,
+) -> Result<(), String> {
+    let (Ok(width), Ok(height)) = (
+        u16::try_from(settings.width),
+        u16::try_from(settings.height),
+    ) else {
+        return Err("GIF frames are at most 65535 pixels wide and high".to_string());
+    };
+    let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(|e| e.to_string())?;
+    let repeat = match settings.looping {
+        true => gif::Repeat::Infinite,
+        false => gif::Repeat::Finite(0),
+    };
+    encoder.set_repeat(repeat).map_err(|e| e.to_string())?;
+    // GIF delays are in hundredths of a second
+    let delay = (settings.frame_delay / 10).min(u16::MAX as u32) as u16;
+    for mut image in images {
+        let mut frame =
+            gif::Frame::from_rgba_speed(width, height, &mut image.pixels, GIF_QUANTIZE_SPEED);
+        frame.delay = delay;
+        encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn write_apng(
+    settings: &AnimationSettings,
+    frame_count: usize,
+    images: impl Iterator<Item = RgbaImage>,
+    file: BufWriter<File>,
+) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(file, settings.width, settings.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // zero plays loops forever
+    let plays = if settings.looping { 0 } else { 1 };
+    encoder
+        .set_animated(frame_count as u32, plays)
+        .map_err(|e| e.to_string())?;
+    let delay = settings.frame_delay.min(u16::MAX as u32) as u16;
+    encoder
+        .set_frame_delay(delay, 1000)
+        .map_err(|e| e.to_string())?;
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for image in images {
+        writer
+            .write_image_data(&image.pixels)
+            .map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentMeta;
+
+    /// A horizontal line `level` segments long.
+    fn line_of(level: usize) -> Geometry {
+        let mut geometry = Geometry::new();
+        let color = Hsv::new(0.0, 0.0, 1.0);
+        for index in 0..level {
+            let meta = SegmentMeta {
+                depth: 0,
+                generation: 0,
+                index,
+                arc_length: 0.0,
+                branch_id: 0,
+            };
+            let x = index as f32 * 10.0;
+            geometry.line(vec2(x, 0.0), vec2(x + 10.0, 0.0), color, 1.0, meta);
+        }
+        geometry
+    }
+
+    fn small(format: AnimationFormat, looping: bool) -> AnimationSettings {
+        AnimationSettings {
+            format,
+            first_level: 1,
+            last_level: 3,
+            frame_delay: 250,
+            looping,
+            width: 40,
+            height: 30,
+            samples: 1,
+            margin: 2.0,
+            ..AnimationSettings::default()
+        }
+    }
+
+    #[test]
+    fn growth_frames_match_the_frame_count() {
+        let mut settings = AnimationSettings {
+            first_level: 2,
+            last_level: 5,
+            ..AnimationSettings::default()
+        };
+        let frames = growth_frames(&settings, line_of);
+        let counts: Vec<usize> = frames.iter().map(Geometry::segment_count).collect();
+        assert_eq!(counts, vec![2, 3, 4, 5]);
+        assert_eq!(settings.frame_count(), 4);
+
+        settings.growth = Growth::Timed;
+        settings.frames = 5;
+        let frames = growth_frames(&settings, line_of);
+        assert_eq!(frames.len(), settings.frame_count());
+        assert_eq!(frames.last().unwrap().segment_count(), 5);
+        assert!(frames[0].segment_count() < 5);
+    }
+
+    #[test]
+    fn frame_transform_fits_the_union_of_the_bounds() {
+        let bounds = [
+            Rect::from_corners(vec2(0.0, 0.0), vec2(10.0, 10.0)),
+            Rect::from_corners(vec2(-10.0, 0.0), vec2(0.0, 20.0)),
+        ];
+        // the union is 20 units square around (0, 10), fitted into 80 pixels
+        let transform = frame_transform(bounds, 100, 100, 10.0);
+        assert_eq!(transform(vec2(0.0, 10.0)), vec2(0.0, 0.0));
+        assert_eq!(transform(vec2(10.0, 20.0)), vec2(40.0, 40.0));
+        assert_eq!(transform(vec2(-10.0, 0.0)), vec2(-40.0, -40.0));
+    }
+
+    #[test]
+    fn gif_loops_and_delays_every_frame() {
+        for looping in [true, false] {
+            let settings = small(AnimationFormat::Gif, looping);
+            let path = std::env::temp_dir().join(format!("l-systems-{}.gif", looping));
+            let frames = growth_frames(&settings, line_of);
+            let mut rendered = Vec::new();
+            let result =
+                export_animation(&settings, frames, &StrokeStyle::default(), &path, |done| {
+                    rendered.push(done)
+                });
+            assert!(result.is_ok(), "{:?}", result);
+            assert_eq!(rendered, vec![1, 2, 3]);
+
+            let bytes = std::fs::read(&path).unwrap();
+            let loops = bytes.windows(11).any(|window| window == b"NETSCAPE2.0");
+            assert_eq!(loops, looping);
+            let mut decoder = gif::DecodeOptions::new().read_info(&bytes[..]).unwrap();
+            let mut delays = Vec::new();
+            while let Some(frame) = decoder.read_next_frame().unwrap() {
+                delays.push(frame.delay);
+            }
+            assert_eq!(delays, vec![25, 25, 25]);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn apng_counts_frames_and_plays() {
+        let settings = small(AnimationFormat::Apng, false);
+        let path = std::env::temp_dir().join("l-systems-animation.png");
+        let frames = growth_frames(&settings, line_of);
+        let result = export_animation(&settings, frames, &StrokeStyle::default(), &path, |_| ());
+        assert!(result.is_ok(), "{:?}", result);
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let info = decoder.read_info().unwrap().info().clone();
+        let control = info.animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 1));
+        let frame = info.frame_control.unwrap();
+        assert_eq!((frame.delay_num, frame.delay_den), (250, 1000));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn no_frames_is_an_error() {
+        let settings = small(AnimationFormat::Gif, true);
+        let path = std::env::temp_dir().join("l-systems-empty.gif");
+        let result = export_animation(
+            &settings,
+            Vec::new(),
+            &StrokeStyle::default(),
+            &path,
+            |_| (),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use nannou::prelude::*;
+
+use crate::{
+    geometry::{Geometry, Polyline},
+    lsystems::SegmentMeta,
+};
+
+/// Segments whose directions differ by at most this many radians can lie on the same line.
+const ANGLE_TOLERANCE: f32 = 1e-4;
+
+/// Removes redundant strokes from turtle output: retraced segments, the parts of segments
+/// that overlap ones already drawn, and collinear runs split into many pieces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cleanup {
+    pub merge_collinear: bool,
+    pub remove_overlaps: bool,
+    /// Points closer than this, or this close to a line, are treated as lying on it.
+    pub epsilon: f32,
+}
+
+/// What a cleanup removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CleanupStats {
+    pub segments_before: usize,
+    pub segments_after: usize,
+    /// Segments dropped because they were already drawn in full or have no length.
+    pub duplicates: usize,
+    /// Segments shortened because part of them was already drawn.
+    pub trimmed: usize,
+    /// Segments joined onto a collinear neighbor.
+    pub merged: usize,
+    pub length_before: f32,
+    pub length_after: f32,
+}
+
+impl CleanupStats {
+    pub fn summary(&self) -> String {
+        let kept = if self.length_before > 0.0 {
+            self.length_after / self.length_before * 100.0
+        } else {
+            100.0
+        };
+        format!(
+            "{} -> {} segments: {} duplicate, {} trimmed, {} merged, {:.0}% of the length kept",
+            self.segments_before,
+            self.segments_after,
+            self.duplicates,
+            self.trimmed,
+            self.merged,
+            kept
+        )
+    }
+}
+
+/// A polyline while it is being cleaned up.
+struct Run {
+    points: Vec<Vec2>,
+    colors: Vec<Hsv>,
+    width: f32,
+    segments: Vec<SegmentMeta>,
+}
+
+/// A line segments have been drawn along, through `origin` in the unit `direction`.
+struct Line {
+    origin: Vec2,
+    direction: Vec2,
+    /// Sorted, disjoint intervals drawn so far, as distances from `origin`.
+    covered: Vec<(f32, f32)>,
+}
+
+impl Line {
+    /// Whether the segment `a..b`, heading in the unit `direction`, lies on the line.
+    fn contains(&self, a: Vec2, b: Vec2, direction: Vec2, epsilon: f32) -> bool {
+        let turn = self.direction.perp_dot(direction).abs();
+        turn <= ANGLE_TOLERANCE.sin()
+            && distance_to_line(a, self.origin, self.direction) <= epsilon
+            && distance_to_line(b, self.origin, self.direction) <= epsilon
+    }
+}
+
+/// The lines drawn so far, bucketed by quantized angle and offset so a segment only has to
+/// be compared against the lines near its own.
+struct Coverage {
+    lines: Vec<Line>,
+    buckets: HashMap<(u32, usize, i64), Vec<usize>>,
+    /// Angle buckets spanning half a turn, each at least `ANGLE_TOLERANCE` wide.
+    angles: usize,
+    /// Width of an offset bucket. Offsets are measured along the normal at the center of
+    /// an angle bucket, so a point on a line slightly off that angle drifts by up to the
+    /// extent of the drawing times the angle tolerance.
+    offset_step: f32,
+}
+
+impl Coverage {
+    fn new(extent: f32, epsilon: f32) -> Self {
+        Coverage {
+            lines: Vec::new(),
+            buckets: HashMap::new(),
+            angles: ((PI / ANGLE_TOLERANCE) as usize).max(1),
+            offset_step: epsilon + extent * ANGLE_TOLERANCE,
+        }
+    }
+    /// The angle bucket of `direction`, which points into the upper half plane.
+    fn angle_bucket(&self, direction: Vec2) -> usize {
+        let angle = direction.y.atan2(direction.x).rem_euclid(PI);
+        ((angle / PI * self.angles as f32) as usize).min(self.angles - 1)
+    }
+    fn offset_bucket(&self, angle: usize, point: Vec2) -> i64 {
+        let center = (angle as f32 + 0.5) / self.angles as f32 * PI;
+        let normal = vec2(-center.sin(), center.cos());
+        (normal.dot(point) / self.offset_step).floor() as i64
+    }
+    /// The line `a..b` lies on, added if nothing has been drawn along it yet.
+    fn line(&mut self, width: f32, a: Vec2, b: Vec2, epsilon: f32) -> usize {
+        let mut direction = (b - a).normalize();
+        if direction.y < 0.0 || (direction.y == 0.0 && direction.x < 0.0) {
+            direction = -direction;
+        }
+        let angle = self.angle_bucket(direction);
+        for step in [self.angles - 1, 0, 1] {
+            let bucket = (angle + step) % self.angles;
+            let offset = self.offset_bucket(bucket, a);
+            for offset in offset - 1..=offset + 1 {
+                let found = self
+                    .buckets
+                    .get(&(width.to_bits(), bucket, offset))
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .find(|&line| self.lines[line].contains(a, b, direction, epsilon));
+                if let Some(line) = found {
+                    return line;
+                }
+            }
+        }
+        let key = (width.to_bits(), angle, self.offset_bucket(angle, a));
+        self.buckets.entry(key).or_default().push(self.lines.len());
+        self.lines.push(Line {
+            origin: a,
+            direction,
+            covered: Vec::new(),
+        });
+        self.lines.len() - 1
+    }
+}
+
+impl Cleanup {
+    pub fn new(merge_collinear: bool, remove_overlaps: bool, epsilon: f32) -> Self {
+        Cleanup {
+            merge_collinear,
+            remove_overlaps,
+            epsilon,
+        }
+    }
+    pub fn default() -> Self {
+        Cleanup::new(false, false, 0.01)
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.merge_collinear || self.remove_overlaps
+    }
+    /// Cleans up the polylines of `geometry` in drawing order, so the first time a stretch
+    /// is drawn is the one kept. Segments count as overlapping when both ends lie within
+    /// epsilon of an earlier line and their directions match, so turtle rounding does not
+    /// keep a retraced stroke. Polygons and points are left alone.
+    pub fn apply(&self, geometry: &mut Geometry) -> CleanupStats {
+        let mut stats = CleanupStats {
+            segments_before: geometry.segment_count(),
+            length_before: length(geometry),
+            ..CleanupStats::default()
+        };
+        let epsilon = self.epsilon.max(f32::EPSILON);
+        let extent = geometry
+            .bounds()
+            .map_or(0.0, |bounds| vec2(bounds.w(), bounds.h()).length());
+
+        let mut coverage = Coverage::new(extent, epsilon);
+        let mut runs: Vec<Run> = Vec::new();
+        for polyline in &geometry.polylines {
+            for (i, pair) in polyline.points.windows(2).enumerate() {
+                let (a, b) = (pair[0], pair[1]);
+                let pieces = if a.distance(b) <= epsilon {
+                    Vec::new()
+                } else if self.remove_overlaps {
+                    undrawn(&mut coverage, polyline.width, a, b, epsilon)
+                } else {
+                    vec![(a, b)]
+                };
+                match pieces.as_slice() {
+                    [] => stats.duplicates += 1,
+                    [piece] if *piece == (a, b) => (),
+                    _ => stats.trimmed += 1,
+                }
+
+                let color = polyline.colors[i + 1];
+                for (start, end) in pieces {
+                    // continue the last run when the piece starts where it ended, like
+                    // `Geometry::line` does
+                    let run = runs.last_mut().filter(|run| {
+                        run.width == polyline.width
+                            && run
+                                .points
+                                .last()
+                                .is_some_and(|last| last.distance(start) <= epsilon)
+                    });
+                    match run {
+                        Some(run) => {
+                            run.points.push(end);
+                            run.colors.push(color);
+                            run.segments.push(polyline.segments[i]);
+                        }
+                        None => {
+                            // a piece cut from inside the segment starts in the segment's color
+                            let start_color = if start == a {
+                                polyline.colors[i]
+                            } else {
+                                color
+                            };
+                            runs.push(Run {
+                                points: vec![start, end],
+                                colors: vec![start_color, color],
+                                width: polyline.width,
+                                segments: vec![polyline.segments[i]],
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.merge_collinear {
+            for run in &mut runs {
+                stats.merged += merge_collinear(run, epsilon);
+            }
+        }
+
+        geometry.polylines = runs
+            .into_iter()
+            .map(|run| Polyline {
+                points: run.points,
+                colors: run.colors,
+                width: run.width,
+                segments: run.segments,
+            })
+            .collect();
+        stats.segments_after = geometry.segment_count();
+        stats.length_after = length(geometry);
+        stats
+    }
+}
+
+fn length(geometry: &Geometry) -> f32 {
+    geometry.segments().map(|(a, b, _)| a.distance(b)).sum()
+}
+
+/// Distance of `point` from the line through `origin` in the unit `direction`.
+fn distance_to_line(point: Vec2, origin: Vec2, direction: Vec2) -> f32 {
+    direction.perp_dot(point - origin).abs()
+}
+
+/// The pieces of `a..b` not yet covered by earlier segments of the same width, in the
+/// direction `a` to `b`, and marks the whole segment as covered. Gaps shorter than
+/// `epsilon` are rounding, not undrawn stroke, and are dropped.
+fn undrawn(
+    coverage: &mut Coverage,
+    width: f32,
+    a: Vec2,
+    b: Vec2,
+    epsilon: f32,
+) -> Vec<(Vec2, Vec2)> {
+    let line = coverage.line(width, a, b, epsilon);
+    let line = &mut coverage.lines[line];
+    let along = |p: Vec2| line.direction.dot(p - line.origin);
+    let (ta, tb) = (along(a), along(b));
+    // places a distance along the line back on the segment itself
+    let at = |t: f32| match t {
+        t if t == ta => a,
+        t if t == tb => b,
+        t => a.lerp(b, (t - ta) / (tb - ta)),
+    };
+    let (start, end) = (ta.min(tb), ta.max(tb));
+
+    let mut gaps = Vec::new();
+    let mut cursor = start;
+    for &(s, e) in line.covered.iter().filter(|&&(s, e)| e > start && s < end) {
+        if s > cursor {
+            gaps.push((cursor, s));
+        }
+        cursor = cursor.max(e);
+    }
+    if cursor < end {
+        gaps.push((cursor, end));
+    }
+    gaps.retain(|&(s, e)| e - s > epsilon);
+
+    // fold the segment into the sorted, disjoint coverage of its line
+    let (mut merged_start, mut merged_end) = (start, end);
+    line.covered.retain(|&(s, e)| {
+        let touches = e >= start - epsilon && s <= end + epsilon;
+        if touches {
+            merged_start = merged_start.min(s);
+            merged_end = merged_end.max(e);
+        }
+        !touches
+    });
+    let index = line.covered.partition_point(|&(s, _)| s < merged_start);
+    line.covered.insert(index, (merged_start, merged_end));
+
+    let mut pieces: Vec<(Vec2, Vec2)> = gaps.into_iter().map(|(s, e)| (at(s), at(e))).collect();
+    if ta > tb {
+        pieces.reverse();
+        pieces
+            .iter_mut()
+            .for_each(|piece| *piece = (piece.1, piece.0));
+    }
+    pieces
+}
+
+/// Drops vertices in the middle of a straight, same colored stretch: each following segment
+/// must head within the angle tolerance of the stretch's first, and end within `epsilon`
+/// of the line it started. A merged segment keeps the metadata of the first segment it
+/// absorbed. Returns the segments removed.
+fn merge_collinear(run: &mut Run, epsilon: f32) -> usize {
+    let count = run.points.len();
+    let mut keep = vec![true; count];
+    let mut previous = run.points[0];
+    let mut direction = (run.points[1] - previous).normalize();
+    for i in 1..count - 1 {
+        let (point, next) = (run.points[i], run.points[i + 1]);
+        let heading = (next - point).normalize();
+        let straight = heading.dot(direction) > 0.0
+            && heading.perp_dot(direction).abs() <= ANGLE_TOLERANCE.sin()
+            && distance_to_line(next, previous, direction) <= epsilon;
+        if straight && run.colors[i] == run.colors[i + 1] {
+            keep[i] = false;
+        } else {
+            previous = point;
+            direction = heading;
+        }
+    }
+
+    let mut points = vec![run.points[0]];
+    let mut colors = vec![run.colors[0]];
+    let mut segments = Vec::new();
+    let mut meta = run.segments[0];
+    for i in 1..count {
+        if !keep[i] {
+            continue;
+        }
+        points.push(run.points[i]);
+        colors.push(run.colors[i]);
+        segments.push(meta);
+        if i < count - 1 {
+            meta = run.segments[i];
+        }
+    }
+    let removed = run.segments.len() - segments.len();
+    run.points = points;
+    run.colors = colors;
+    run.segments = segments;
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(index: usize) -> SegmentMeta {
+        SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index,
+            arc_length: 0.0,
+            branch_id: 0,
+        }
+    }
+
+    /// A turtle walk from the origin heading `start` degrees, turning by each of `turns`
+    /// degrees before a unit step, with the rounding a real turtle accumulates.
+    fn walk(start: f32, turns: &[f32]) -> Geometry {
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        let mut geometry = Geometry::new();
+        let (mut pos, mut heading) = (Vec2::ZERO, start.to_radians());
+        for (i, turn) in turns.iter().enumerate() {
+            heading += turn.to_radians();
+            let next = pos + vec2(heading.cos(), heading.sin());
+            geometry.line(pos, next, color, 1.0, meta(i));
+            pos = next;
+        }
+        geometry
+    }
+
+    fn starts() -> impl Iterator<Item = f32> {
+        (0..24).map(|i| i as f32 * 15.7 - 3.0)
+    }
+
+    #[test]
+    fn retraced_sixty_degree_segment_is_a_duplicate() {
+        let cleanup = Cleanup::new(false, true, 0.01);
+        for start in starts() {
+            // out along 60 degrees, around a triangle and back down the first side
+            let mut geometry = walk(start, &[60.0, 120.0, 120.0, 120.0]);
+            let stats = cleanup.apply(&mut geometry);
+            assert_eq!(stats.duplicates, 1, "start {}", start);
+            assert_eq!(stats.segments_after, 3, "start {}", start);
+        }
+    }
+
+    #[test]
+    fn partly_retraced_segment_is_trimmed() {
+        let cleanup = Cleanup::new(false, true, 0.01);
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        for start in starts() {
+            let direction = vec2(start.to_radians().cos(), start.to_radians().sin());
+            let mut geometry = Geometry::new();
+            geometry.line(Vec2::ZERO, direction * 2.0, color, 1.0, meta(0));
+            geometry.line(direction * 3.0, direction * 1.0, color, 1.0, meta(1));
+            let stats = cleanup.apply(&mut geometry);
+            assert_eq!(stats.trimmed, 1, "start {}", start);
+            assert!((stats.length_after - 3.0).abs() < 1e-4, "start {}", start);
+            let piece = &geometry.polylines[1].points;
+            assert!(piece[1].distance(direction * 2.0) < 1e-4, "start {}", start);
+        }
+    }
+
+    #[test]
+    fn collinear_runs_merge_at_any_angle() {
+        let cleanup = Cleanup::new(true, false, 0.01);
+        for start in starts() {
+            let mut geometry = walk(start + 25.0, &[0.0; 6]);
+            let end = *geometry.polylines[0].points.last().unwrap();
+            let stats = cleanup.apply(&mut geometry);
+            assert_eq!(stats.merged, 5, "start {}", start);
+            let polyline = &geometry.polylines[0];
+            assert_eq!(polyline.points, vec![Vec2::ZERO, end]);
+            assert_eq!(polyline.segments, vec![meta(0)]);
+        }
+    }
+
+    #[test]
+    fn bends_and_parallel_lines_are_kept() {
+        let cleanup = Cleanup::new(true, true, 0.01);
+        let mut bent = walk(25.0, &[0.0, 1.0, 0.0]);
+        let stats = cleanup.apply(&mut bent);
+        assert_eq!((stats.merged, stats.segments_after), (1, 2));
+
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        let mut parallel = Geometry::new();
+        parallel.line(vec2(0.0, 0.0), vec2(1.0, 0.0), color, 1.0, meta(0));
+        parallel.line(vec2(0.0, 0.05), vec2(1.0, 0.05), color, 1.0, meta(1));
+        let stats = cleanup.apply(&mut parallel);
+        assert_eq!((stats.duplicates, stats.segments_after), (0, 2));
+    }
+
+    #[test]
+    fn overlap_found_far_along_a_line() {
+        let cleanup = Cleanup::new(false, true, 0.01);
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        let direction = vec2(37f32.to_radians().cos(), 37f32.to_radians().sin());
+        let mut geometry = Geometry::new();
+        geometry.line(Vec2::ZERO, direction * 1000.0, color, 1.0, meta(0));
+        geometry.line(vec2(5.0, -300.0), vec2(6.0, -300.0), color, 1.0, meta(1));
+        geometry.line(direction * 700.0, direction * 600.0, color, 1.0, meta(2));
+        let stats = cleanup.apply(&mut geometry);
+        assert_eq!((stats.duplicates, stats.segments_after), (1, 2));
+    }
+}
@@ -0,0 +1,202 @@
+use nannou::color::Mix;
+use nannou::prelude::*;
+
+use crate::lsystems::TurtleState;
+
+/// The piece of turtle state a palette maps to a color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSource {
+    /// Ignore the gradient and use the system's own color.
+    Fixed,
+    ColorIndex,
+    Depth,
+    Distance,
+    Generation,
+    Heading,
+}
+
+impl ColorSource {
+    pub const ALL: [ColorSource; 6] = [
+        ColorSource::Fixed,
+        ColorSource::ColorIndex,
+        ColorSource::Depth,
+        ColorSource::Distance,
+        ColorSource::Generation,
+        ColorSource::Heading,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorSource::Fixed => "Fixed",
+            ColorSource::ColorIndex => "Color Index (')",
+            ColorSource::Depth => "Bracket Depth",
+            ColorSource::Distance => "Distance",
+            ColorSource::Generation => "Generation",
+            ColorSource::Heading => "Heading",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    /// (position in 0..=1, color) pairs, in any order.
+    pub stops: Vec<(f32, Hsv)>,
+}
+
+impl Gradient {
+    pub fn new(stops: Vec<(f32, Hsv)>) -> Self {
+        Gradient { stops }
+    }
+    pub fn sample(&self, t: f32) -> Option<Hsv> {
+        let below = self
+            .stops
+            .iter()
+            .filter(|(pos, _)| *pos <= t)
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        let above = self
+            .stops
+            .iter()
+            .filter(|(pos, _)| *pos >= t)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        match (below, above) {
+            (Some(&(start, from)), Some(&(end, to))) if end > start => {
+                Some(from.mix(&to, (t - start) / (end - start)))
+            }
+            (Some(&(_, color)), _) | (None, Some(&(_, color))) => Some(color),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub source: ColorSource,
+    pub gradient: Gradient,
+    /// Multiplies the source value before it is looked up in the gradient.
+    pub scale: f32,
+    /// Wrap around the gradient instead of clamping to its ends.
+    pub repeat: bool,
+}
+
+impl Palette {
+    pub fn new(source: ColorSource, gradient: Gradient, scale: f32, repeat: bool) -> Self {
+        Palette {
+            source,
+            gradient,
+            scale,
+            repeat,
+        }
+    }
+    pub fn default() -> Self {
+        Palette::new(
+            ColorSource::Fixed,
+            Gradient::new(vec![(0.0, hsv(0.08, 0.8, 0.4)), (1.0, hsv(0.3, 0.9, 0.9))]),
+            0.1,
+            false,
+        )
+    }
+    /// The color for a segment drawn in `state`, or `fixed` when the palette is not in use.
+    pub fn color(&self, state: &TurtleState, fixed: Hsv) -> Hsv {
+        let value = match self.source {
+            ColorSource::Fixed => return fixed,
+            ColorSource::ColorIndex => state.color_index as f32,
+            ColorSource::Depth => state.depth as f32,
+            ColorSource::Distance => state.distance,
+            ColorSource::Generation => state.generation as f32,
+            ColorSource::Heading => state.angle.rem_euclid(TAU) / TAU,
+        };
+        let t = value * self.scale;
+        let t = if self.repeat {
+            t.rem_euclid(1.0)
+        } else {
+            t.clamp(0.0, 1.0)
+        };
+
+        self.gradient.sample(t).unwrap_or(fixed)
+    }
+}
+
+/// The 8-bit sRGB components of `color`, as shown on screen.
+pub fn srgb_bytes(color: Hsv) -> [u8; 3] {
+    let rgb = Srgb::from(color);
+    [rgb.red, rgb.green, rgb.blue].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LSystemDrawingParamaters;
+
+    /// Black at 0 to white at 1, so the sampled value is the position.
+    fn ramp() -> Gradient {
+        Gradient::new(vec![(1.0, hsv(0.0, 0.0, 1.0)), (0.0, hsv(0.0, 0.0, 0.0))])
+    }
+
+    fn assert_value(color: Hsv, value: f32) {
+        assert!(
+            (color.value - value).abs() < 1e-4,
+            "{:?} != {}",
+            color,
+            value
+        );
+    }
+
+    #[test]
+    fn gradient_interpolates_unsorted_stops() {
+        let gradient = ramp();
+        assert_value(gradient.sample(0.25).unwrap(), 0.25);
+        assert_value(gradient.sample(1.0).unwrap(), 1.0);
+        let gradient = Gradient::new(vec![
+            (0.5, hsv(0.0, 0.0, 1.0)),
+            (1.0, hsv(0.0, 0.0, 0.0)),
+            (0.0, hsv(0.0, 0.0, 0.0)),
+        ]);
+        assert_value(gradient.sample(0.25).unwrap(), 0.5);
+        assert_value(gradient.sample(0.75).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn gradient_holds_its_end_colors() {
+        let gradient = Gradient::new(vec![(0.2, hsv(0.0, 0.0, 0.3)), (0.8, hsv(0.0, 0.0, 0.6))]);
+        assert_value(gradient.sample(0.0).unwrap(), 0.3);
+        assert_value(gradient.sample(1.0).unwrap(), 0.6);
+        assert_eq!(Gradient::new(vec![]).sample(0.5), None);
+    }
+
+    #[test]
+    fn palette_reads_each_source() {
+        let mut state = LSystemDrawingParamaters::new(vec2(0.0, 0.0), PI / 2.0).state;
+        state.color_index = 3;
+        state.depth = 5;
+        state.distance = 2.0;
+        state.generation = 7;
+        let fixed = hsv(0.5, 1.0, 0.9);
+        let expected = [
+            (ColorSource::ColorIndex, 0.3),
+            (ColorSource::Depth, 0.5),
+            (ColorSource::Distance, 0.2),
+            (ColorSource::Generation, 0.7),
+            (ColorSource::Heading, 0.025),
+        ];
+        for (source, value) in expected {
+            let palette = Palette::new(source, ramp(), 0.1, false);
+            assert_value(palette.color(&state, fixed), value);
+        }
+        let palette = Palette::new(ColorSource::Fixed, ramp(), 0.1, false);
+        assert_eq!(palette.color(&state, fixed), fixed);
+        let palette = Palette::new(ColorSource::Depth, Gradient::new(vec![]), 0.1, false);
+        assert_eq!(palette.color(&state, fixed), fixed);
+    }
+
+    #[test]
+    fn palette_clamps_or_repeats() {
+        let mut state = LSystemDrawingParamaters::new(vec2(0.0, 0.0), 0.0).state;
+        state.color_index = 13;
+        let fixed = hsv(0.5, 1.0, 0.9);
+        let clamped = Palette::new(ColorSource::ColorIndex, ramp(), 0.1, false);
+        assert_value(clamped.color(&state, fixed), 1.0);
+        let repeated = Palette::new(ColorSource::ColorIndex, ramp(), 0.1, true);
+        assert_value(repeated.color(&state, fixed), 0.3);
+    }
+}
@@ -0,0 +1,587 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use nannou::prelude::*;
+
+use crate::{color_palette::srgb_bytes, geometry::Geometry};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DxfUnits {
+    Millimetres,
+    Inches,
+}
+
+impl DxfUnits {
+    pub const ALL: [DxfUnits; 2] = [DxfUnits::Millimetres, DxfUnits::Inches];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DxfUnits::Millimetres => "mm",
+            DxfUnits::Inches => "in",
+        }
+    }
+    /// `$INSUNITS` code.
+    fn code(&self) -> u32 {
+        match self {
+            DxfUnits::Millimetres => 4,
+            DxfUnits::Inches => 1,
+        }
+    }
+}
+
+/// What puts a segment on its own layer, so cutters can assign operations per layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DxfLayering {
+    Color,
+    Depth,
+}
+
+impl DxfLayering {
+    pub const ALL: [DxfLayering; 2] = [DxfLayering::Color, DxfLayering::Depth];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DxfLayering::Color => "By Color",
+            DxfLayering::Depth => "By Depth",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DxfSettings {
+    pub units: DxfUnits,
+    /// Physical length of the longer side of the drawing, in `units`.
+    pub size: f32,
+    pub layering: DxfLayering,
+}
+
+impl DxfSettings {
+    pub fn new(units: DxfUnits, size: f32, layering: DxfLayering) -> Self {
+        DxfSettings {
+            units,
+            size,
+            layering,
+        }
+    }
+    pub fn default() -> Self {
+        DxfSettings::new(DxfUnits::Millimetres, 200.0, DxfLayering::Color)
+    }
+}
+
+/// An R2000 DXF with every stroke as an `LWPOLYLINE`, polygons as closed polylines and
+/// points as circles, scaled to `settings.size` with the bottom left of the bounds at
+/// the origin. Every table, block and object AutoCAD requires is written, with handles
+/// and owners, so strict readers accept the file.
+pub fn geometry_dxf(geometry: &Geometry, settings: &DxfSettings) -> String {
+    let bounds = geometry.bounds().unwrap_or(Rect::from_w_h(0.0, 0.0));
+    let longest = bounds.w().max(bounds.h());
+    let scale = if longest > 0.0 {
+        settings.size / longest
+    } else {
+        1.0
+    };
+    let origin = vec2(bounds.left(), bounds.bottom());
+    let place = |p: Vec2| (p - origin) * scale;
+
+    // layer name to color, and every entity with its layer
+    let mut layers = BTreeMap::new();
+    let mut entities = Vec::new();
+    for polyline in &geometry.polylines {
+        let mut run: Option<(String, Vec<Vec2>)> = None;
+        for (i, pair) in polyline.points.windows(2).enumerate() {
+            let color = polyline.colors[i + 1];
+            let layer = match settings.layering {
+                DxfLayering::Color => color_layer(color),
+                DxfLayering::Depth => format!("DEPTH_{}", polyline.segments[i].depth),
+            };
+            layers.entry(layer.clone()).or_insert(color);
+            match &mut run {
+                Some((name, points)) if *name == layer => points.push(place(pair[1])),
+                _ => {
+                    entities.extend(
+                        run.take()
+                            .map(|(name, points)| Entity::Polyline(name, points, false)),
+                    );
+                    run = Some((layer, vec![place(pair[0]), place(pair[1])]));
+                }
+            }
+        }
+        entities.extend(run.map(|(name, points)| Entity::Polyline(name, points, false)));
+    }
+    for polygon in &geometry.polygons {
+        let layer = match settings.layering {
+            DxfLayering::Color => color_layer(polygon.color),
+            DxfLayering::Depth => "SURFACES".to_string(),
+        };
+        layers.entry(layer.clone()).or_insert(polygon.color);
+        let points = polygon.points.iter().map(|&p| place(p)).collect();
+        entities.push(Entity::Polyline(layer, points, true));
+    }
+    for point in &geometry.points {
+        let layer = match settings.layering {
+            DxfLayering::Color => color_layer(point.color),
+            DxfLayering::Depth => "POINTS".to_string(),
+        };
+        layers.entry(layer.clone()).or_insert(point.color);
+        entities.push(Entity::Circle(
+            layer,
+            place(point.pos),
+            point.radius * scale,
+        ));
+    }
+
+    // layer 0 always exists, and holds the block definitions
+    let white = Hsv::new(0.0, 0.0, 1.0);
+    layers.entry("0".to_string()).or_insert(white);
+
+    // the header needs the next free handle, so it is written after everything else
+    let mut dxf = Dxf::new();
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "CLASSES");
+    dxf.pair(0, "ENDSEC");
+
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "TABLES");
+    let table = dxf.table("VPORT", 1);
+    dxf.record("VPORT", "AcDbViewportTableRecord", table, "*ACTIVE");
+    let extents = place(vec2(bounds.right(), bounds.top()));
+    dxf.pair(12, format!("{:.4}", extents.x / 2.0));
+    dxf.pair(22, format!("{:.4}", extents.y / 2.0));
+    dxf.pair(40, format!("{:.4}", extents.y.max(extents.x).max(1.0)));
+    dxf.pair(41, "1.0");
+    dxf.pair(0, "ENDTAB");
+
+    let table = dxf.table("LTYPE", 3);
+    for (name, description) in [("ByBlock", ""), ("ByLayer", ""), (CONTINUOUS, "Solid line")] {
+        dxf.record("LTYPE", "AcDbLinetypeTableRecord", table, name);
+        dxf.pair(3, description);
+        dxf.pair(72, 65);
+        dxf.pair(73, 0);
+        dxf.pair(40, "0.0");
+    }
+    dxf.pair(0, "ENDTAB");
+
+    let table = dxf.table("LAYER", layers.len());
+    for (name, color) in &layers {
+        dxf.record("LAYER", "AcDbLayerTableRecord", table, name);
+        dxf.pair(62, nearest_aci(*color));
+        dxf.pair(6, CONTINUOUS);
+    }
+    dxf.pair(0, "ENDTAB");
+
+    let table = dxf.table("STYLE", 1);
+    dxf.record("STYLE", "AcDbTextStyleTableRecord", table, "Standard");
+    dxf.pair(40, "0.0");
+    dxf.pair(41, "1.0");
+    dxf.pair(50, "0.0");
+    dxf.pair(71, 0);
+    dxf.pair(42, "2.5");
+    dxf.pair(3, "txt");
+    dxf.pair(4, "");
+    dxf.pair(0, "ENDTAB");
+
+    for name in ["VIEW", "UCS"] {
+        dxf.table(name, 0);
+        dxf.pair(0, "ENDTAB");
+    }
+
+    let table = dxf.table("APPID", 1);
+    dxf.record("APPID", "AcDbRegAppTableRecord", table, "ACAD");
+    dxf.pair(0, "ENDTAB");
+
+    // dimension styles are the one table whose handles use code 105
+    let table = dxf.table("DIMSTYLE", 1);
+    dxf.pair(100, "AcDbDimStyleTable");
+    dxf.pair(0, "DIMSTYLE");
+    let handle = dxf.next_handle();
+    dxf.pair(105, format!("{:X}", handle));
+    dxf.pair(330, format!("{:X}", table));
+    dxf.pair(100, "AcDbSymbolTableRecord");
+    dxf.pair(100, "AcDbDimStyleTableRecord");
+    dxf.pair(2, "Standard");
+    dxf.pair(70, 0);
+    dxf.pair(0, "ENDTAB");
+
+    let table = dxf.table("BLOCK_RECORD", 2);
+    let model_space = dxf.record("BLOCK_RECORD", "AcDbBlockTableRecord", table, MODEL_SPACE);
+    let paper_space = dxf.record("BLOCK_RECORD", "AcDbBlockTableRecord", table, PAPER_SPACE);
+    dxf.pair(0, "ENDTAB");
+    dxf.pair(0, "ENDSEC");
+
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "BLOCKS");
+    for (name, owner) in [(MODEL_SPACE, model_space), (PAPER_SPACE, paper_space)] {
+        let paper = owner == paper_space;
+        dxf.block_marker("BLOCK", owner, paper);
+        dxf.pair(100, "AcDbBlockBegin");
+        dxf.pair(2, name);
+        dxf.pair(70, 0);
+        dxf.point(Vec2::ZERO);
+        dxf.pair(3, name);
+        dxf.pair(1, "");
+        dxf.block_marker("ENDBLK", owner, paper);
+        dxf.pair(100, "AcDbBlockEnd");
+    }
+    dxf.pair(0, "ENDSEC");
+
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "ENTITIES");
+    for entity in entities {
+        match entity {
+            Entity::Polyline(layer, points, closed) => {
+                dxf.entity("LWPOLYLINE", model_space, &layer);
+                dxf.pair(100, "AcDbPolyline");
+                dxf.pair(90, points.len());
+                dxf.pair(70, if closed { 1 } else { 0 });
+                for p in points {
+                    dxf.pair(10, format!("{:.4}", p.x));
+                    dxf.pair(20, format!("{:.4}", p.y));
+                }
+            }
+            Entity::Circle(layer, center, radius) => {
+                dxf.entity("CIRCLE", model_space, &layer);
+                dxf.pair(100, "AcDbCircle");
+                dxf.point(center);
+                dxf.pair(40, format!("{:.4}", radius));
+            }
+        }
+    }
+    dxf.pair(0, "ENDSEC");
+
+    // the named object dictionary, with the group dictionary AutoCAD expects in it
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "OBJECTS");
+    let root = dxf.next_handle();
+    let groups = dxf.next_handle();
+    dxf.pair(0, "DICTIONARY");
+    dxf.pair(5, format!("{:X}", root));
+    dxf.pair(330, 0);
+    dxf.pair(100, "AcDbDictionary");
+    dxf.pair(281, 1);
+    dxf.pair(3, "ACAD_GROUP");
+    dxf.pair(350, format!("{:X}", groups));
+    dxf.pair(0, "DICTIONARY");
+    dxf.pair(5, format!("{:X}", groups));
+    dxf.pair(330, format!("{:X}", root));
+    dxf.pair(100, "AcDbDictionary");
+    dxf.pair(281, 1);
+    dxf.pair(0, "ENDSEC");
+    dxf.pair(0, "EOF");
+
+    let mut header = Dxf::new();
+    header.pair(0, "SECTION");
+    header.pair(2, "HEADER");
+    header.pair(9, "$ACADVER");
+    header.pair(1, "AC1015");
+    header.pair(9, "$HANDSEED");
+    header.pair(5, format!("{:X}", dxf.handle + 1));
+    header.pair(9, "$INSUNITS");
+    header.pair(70, settings.units.code());
+    header.pair(9, "$EXTMIN");
+    header.point(Vec2::ZERO);
+    header.pair(9, "$EXTMAX");
+    header.point(extents);
+    header.pair(0, "ENDSEC");
+    header.out + &dxf.out
+}
+
+enum Entity {
+    Polyline(String, Vec<Vec2>, bool),
+    Circle(String, Vec2, f32),
+}
+
+const CONTINUOUS: &str = "Continuous";
+const MODEL_SPACE: &str = "*Model_Space";
+const PAPER_SPACE: &str = "*Paper_Space";
+
+/// DXF group code and value lines, with a counter for handles.
+struct Dxf {
+    out: String,
+    /// Last handle given out.
+    handle: u32,
+}
+
+impl Dxf {
+    fn new() -> Self {
+        Dxf {
+            out: String::new(),
+            handle: 0,
+        }
+    }
+    fn pair(&mut self, code: u32, value: impl std::fmt::Display) {
+        write!(self.out, "{}\n{}\n", code, value).unwrap();
+    }
+    fn point(&mut self, p: Vec2) {
+        self.pair(10, format!("{:.4}", p.x));
+        self.pair(20, format!("{:.4}", p.y));
+        self.pair(30, "0.0");
+    }
+    fn next_handle(&mut self) -> u32 {
+        self.handle += 1;
+        self.handle
+    }
+    /// Writes a new handle and the handle of the object owning it.
+    fn handles(&mut self, owner: u32) -> u32 {
+        let handle = self.next_handle();
+        self.pair(5, format!("{:X}", handle));
+        self.pair(330, format!("{:X}", owner));
+        handle
+    }
+    /// Starts a symbol table holding `count` records and returns its handle.
+    fn table(&mut self, name: &str, count: usize) -> u32 {
+        self.pair(0, "TABLE");
+        self.pair(2, name);
+        let handle = self.handles(0);
+        self.pair(100, "AcDbSymbolTable");
+        self.pair(70, count);
+        handle
+    }
+    /// Starts a record of `table` and returns its handle.
+    fn record(&mut self, kind: &str, subclass: &str, table: u32, name: &str) -> u32 {
+        self.pair(0, kind);
+        let handle = self.handles(table);
+        self.pair(100, "AcDbSymbolTableRecord");
+        self.pair(100, subclass);
+        self.pair(2, name);
+        self.pair(70, 0);
+        handle
+    }
+    /// Starts an entity on `layer` in the block whose record is `owner`.
+    fn entity(&mut self, kind: &str, owner: u32, layer: &str) {
+        self.pair(0, kind);
+        self.handles(owner);
+        self.pair(100, "AcDbEntity");
+        self.pair(8, layer);
+    }
+    /// Starts the `BLOCK` or `ENDBLK` around a block definition, on layer 0.
+    fn block_marker(&mut self, kind: &str, owner: u32, paper_space: bool) {
+        self.pair(0, kind);
+        self.handles(owner);
+        self.pair(100, "AcDbEntity");
+        if paper_space {
+            self.pair(67, 1);
+        }
+        self.pair(8, "0");
+    }
+}
+
+fn color_layer(color: Hsv) -> String {
+    let [r, g, b] = srgb_bytes(color);
+    format!("COLOR_{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// Closest of the seven standard AutoCAD color indices, which every DXF reader knows.
+fn nearest_aci(color: Hsv) -> u32 {
+    let [r, g, b] = srgb_bytes(color).map(|c| c as i32);
+    let standard = [
+        (1, [255, 0, 0]),
+        (2, [255, 255, 0]),
+        (3, [0, 255, 0]),
+        (4, [0, 255, 255]),
+        (5, [0, 0, 255]),
+        (6, [255, 0, 255]),
+        (7, [255, 255, 255]),
+    ];
+    standard
+        .iter()
+        .min_by_key(|(_, [sr, sg, sb])| (r - sr).pow(2) + (g - sg).pow(2) + (b - sb).pow(2))
+        .map(|(index, _)| *index)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::lsystems::SegmentMeta;
+
+    fn geometry() -> Geometry {
+        let meta = |depth| SegmentMeta {
+            depth,
+            generation: 0,
+            index: 0,
+            arc_length: 0.0,
+            branch_id: 0,
+        };
+        let (red, blue) = (Hsv::new(0.0, 1.0, 1.0), Hsv::new(240.0, 1.0, 1.0));
+        let mut geometry = Geometry::new();
+        geometry.line(vec2(0.0, 0.0), vec2(10.0, 0.0), red, 2.0, meta(0));
+        geometry.line(vec2(10.0, 0.0), vec2(10.0, 10.0), blue, 2.0, meta(1));
+        geometry.polygon(vec![vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)], red);
+        geometry.point(vec2(5.0, 5.0), 1.0, blue);
+        geometry
+    }
+
+    fn pairs(dxf: &str) -> Vec<(u32, &str)> {
+        let lines: Vec<&str> = dxf.lines().collect();
+        assert_eq!(lines.len() % 2, 0);
+        lines
+            .chunks(2)
+            .map(|pair| (pair[0].trim().parse().unwrap(), pair[1]))
+            .collect()
+    }
+
+    /// Each object as its type and group codes, in file order.
+    fn objects<'a>(pairs: &[(u32, &'a str)]) -> Vec<(&'a str, Vec<(u32, &'a str)>)> {
+        let mut objects: Vec<(&str, Vec<(u32, &str)>)> = Vec::new();
+        for &(code, value) in pairs {
+            match (code, objects.last_mut()) {
+                (0, _) => objects.push((value, Vec::new())),
+                (_, Some((_, codes))) => codes.push((code, value)),
+                (_, None) => panic!("group code {} outside any object", code),
+            }
+        }
+        objects
+    }
+
+    fn value<'a>(codes: &[(u32, &'a str)], code: u32) -> Option<&'a str> {
+        codes.iter().find(|&&(c, _)| c == code).map(|&(_, v)| v)
+    }
+
+    #[test]
+    fn sections_in_order() {
+        let dxf = geometry_dxf(&geometry(), &DxfSettings::default());
+        let pairs = pairs(&dxf);
+        let sections: Vec<&str> = pairs
+            .windows(2)
+            .filter(|w| w[0] == (0, "SECTION"))
+            .map(|w| w[1].1)
+            .collect();
+        assert_eq!(
+            sections,
+            ["HEADER", "CLASSES", "TABLES", "BLOCKS", "ENTITIES", "OBJECTS"]
+        );
+        assert_eq!(pairs.last(), Some(&(0, "EOF")));
+        let tables: Vec<&str> = pairs
+            .windows(2)
+            .filter(|w| w[0] == (0, "TABLE"))
+            .map(|w| w[1].1)
+            .collect();
+        assert_eq!(
+            tables,
+            [
+                "VPORT",
+                "LTYPE",
+                "LAYER",
+                "STYLE",
+                "VIEW",
+                "UCS",
+                "APPID",
+                "DIMSTYLE",
+                "BLOCK_RECORD"
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_are_unique_and_below_the_seed() {
+        let dxf = geometry_dxf(&geometry(), &DxfSettings::default());
+        let objects = objects(&pairs(&dxf));
+        let header = &objects[0].1;
+        let at = header.iter().position(|&p| p == (9, "$HANDSEED")).unwrap();
+        let seed = u32::from_str_radix(header[at + 1].1, 16).unwrap();
+
+        let mut handles = HashSet::new();
+        // the header section holds the seed itself
+        for (kind, codes) in &objects[1..] {
+            let code = if *kind == "DIMSTYLE" { 105 } else { 5 };
+            if let Some(handle) = value(codes, code) {
+                let handle = u32::from_str_radix(handle, 16).unwrap();
+                assert!(
+                    handle > 0 && handle < seed,
+                    "{} has handle {:X}",
+                    kind,
+                    handle
+                );
+                assert!(
+                    handles.insert(handle),
+                    "{} reuses handle {:X}",
+                    kind,
+                    handle
+                );
+            }
+        }
+        // every table, record, block marker, entity and dictionary has one
+        assert_eq!(handles.len(), seed as usize - 1);
+    }
+
+    #[test]
+    fn owners_point_at_existing_objects() {
+        let dxf = geometry_dxf(&geometry(), &DxfSettings::default());
+        let objects = objects(&pairs(&dxf));
+        let by_handle: HashMap<&str, &str> = objects
+            .iter()
+            .filter_map(|(kind, codes)| value(codes, 5).or(value(codes, 105)).map(|h| (h, *kind)))
+            .collect();
+        let record_named = |name: &str| {
+            objects
+                .iter()
+                .find(|(kind, codes)| *kind == "BLOCK_RECORD" && value(codes, 2) == Some(name))
+                .and_then(|(_, codes)| value(codes, 5))
+                .unwrap()
+        };
+        let model_space = record_named(MODEL_SPACE);
+
+        let entities = ["LWPOLYLINE", "CIRCLE"];
+        let mut count = 0;
+        for (kind, codes) in &objects {
+            let Some(owner) = value(codes, 330) else {
+                continue;
+            };
+            if owner != "0" {
+                assert!(by_handle.contains_key(owner), "{} owned by {}", kind, owner);
+            }
+            if entities.contains(kind) {
+                assert_eq!(owner, model_space);
+                count += 1;
+            }
+        }
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn layers_use_defined_linetypes() {
+        let dxf = geometry_dxf(&geometry(), &DxfSettings::default());
+        let objects = objects(&pairs(&dxf));
+        let names = |wanted: &str| -> Vec<&str> {
+            objects
+                .iter()
+                .filter(|(kind, _)| *kind == wanted)
+                .filter_map(|(_, codes)| value(codes, 2))
+                .filter(|&name| name != wanted)
+                .collect()
+        };
+        let linetypes = names("LTYPE");
+        assert_eq!(linetypes, ["ByBlock", "ByLayer", "Continuous"]);
+        let layers = names("LAYER");
+        assert!(layers.contains(&"0"));
+        for (kind, codes) in &objects {
+            if *kind == "LAYER" {
+                assert!(linetypes.contains(&value(codes, 6).unwrap()));
+            }
+            if let (Some(layer), true) = (value(codes, 8), ["LWPOLYLINE", "CIRCLE"].contains(kind))
+            {
+                assert!(
+                    layers.contains(&layer),
+                    "{} on undefined layer {}",
+                    kind,
+                    layer
+                );
+            }
+        }
+        assert_eq!(names("BLOCK_RECORD"), [MODEL_SPACE, PAPER_SPACE]);
+        assert_eq!(names("BLOCK"), [MODEL_SPACE, PAPER_SPACE]);
+    }
+
+    #[test]
+    fn depth_layers() {
+        let settings = DxfSettings::new(DxfUnits::Inches, 10.0, DxfLayering::Depth);
+        let dxf = geometry_dxf(&geometry(), &settings);
+        for layer in ["DEPTH_0", "DEPTH_1", "SURFACES", "POINTS"] {
+            assert!(dxf.contains(&format!("\n8\n{}\n", layer)));
+        }
+        assert!(dxf.contains("$INSUNITS\n70\n1\n"));
+        // scaled so the longer side is 10 inches
+        assert!(dxf.contains("$EXTMAX\n10\n10.0000\n20\n10.0000\n"));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fmt, fs, path::Path};
+
+use nannou::prelude::*;
+
+use crate::{
+    dxf_export::{geometry_dxf, DxfSettings},
+    geometry::Geometry,
+    mesh::Mesh,
+    mesh_export::{SolidSettings, TreeSolids},
+    pdf_export::{geometry_eps, geometry_pdf, PageLayout},
+    plotter_export::{PlotPlan, PlotterSettings},
+    poster::{render_poster, PosterSettings},
+    raster::Raster,
+    stroke::StrokeStyle,
+    svg_export::geometry_svg,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Svg,
+    Png,
+    Poster,
+    Pdf,
+    Eps,
+    GCode,
+    Hpgl,
+    Dxf,
+    Obj,
+    Stl,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 10] = [
+        ExportFormat::Svg,
+        ExportFormat::Png,
+        ExportFormat::Poster,
+        ExportFormat::Pdf,
+        ExportFormat::Eps,
+        ExportFormat::GCode,
+        ExportFormat::Hpgl,
+        ExportFormat::Dxf,
+        ExportFormat::Obj,
+        ExportFormat::Stl,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Svg => "SVG",
+            ExportFormat::Png => "PNG",
+            ExportFormat::Poster => "Poster PNG",
+            ExportFormat::Pdf => "PDF",
+            ExportFormat::Eps => "EPS",
+            ExportFormat::GCode => "G-code",
+            ExportFormat::Hpgl => "HPGL",
+            ExportFormat::Dxf => "DXF",
+            ExportFormat::Obj => "OBJ",
+            ExportFormat::Stl => "STL",
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Svg => "svg",
+            ExportFormat::Png => "png",
+            ExportFormat::Poster => "png",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Eps => "eps",
+            ExportFormat::GCode => "gcode",
+            ExportFormat::Hpgl => "hpgl",
+            ExportFormat::Dxf => "dxf",
+            ExportFormat::Obj => "obj",
+            ExportFormat::Stl => "stl",
+        }
+    }
+}
+
+/// Format specific settings, kept together so switching formats doesn't lose them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub background: Hsv,
+    /// Image size in pixels. The view is scaled to fit and centered, like the window does
+    /// when resized.
+    pub png_width: u32,
+    pub png_height: u32,
+    /// Anti-aliasing samples per pixel along each axis.
+    pub png_samples: u32,
+    pub poster: PosterSettings,
+    /// Paper for print and plotter formats.
+    pub page: PageLayout,
+    pub plotter: PlotterSettings,
+    pub dxf: DxfSettings,
+    pub solid: SolidSettings,
+}
+
+impl ExportOptions {
+    pub fn default() -> Self {
+        ExportOptions {
+            background: Hsv::new(0.0, 0.0, 0.0),
+            png_width: 1920,
+            png_height: 1080,
+            png_samples: 4,
+            poster: PosterSettings::default(),
+            page: PageLayout::default(),
+            plotter: PlotterSettings::default(),
+            dxf: DxfSettings::default(),
+            solid: SolidSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    Io(String),
+    Encode(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(error) => write!(f, "could not write export: {}", error),
+            ExportError::Encode(error) => write!(f, "could not encode export: {}", error),
+        }
+    }
+}
+
+/// Writes `geometry` stroked with `stroke` to `path` in `format` and describes the result.
+/// Raster formats show the part of the drawing inside `view`, like the window does.
+pub fn export(
+    format: ExportFormat,
+    geometry: &Geometry,
+    stroke: &StrokeStyle,
+    view: Rect,
+    options: &ExportOptions,
+    path: impl AsRef<Path>,
+) -> Result<String, ExportError> {
+    let path = path.as_ref();
+    let mut summary = format!("wrote {}", path.display());
+    match format {
+        ExportFormat::Svg => write(path, geometry_svg(geometry, stroke))?,
+        ExportFormat::Png => Raster::new(options.png_width, options.png_height, view)
+            .with_samples(options.png_samples)
+            .with_background(options.background)
+            .render(&Mesh::from_geometry(geometry, stroke))
+            .save_png(path)?,
+        ExportFormat::Poster => {
+            summary = render_poster(geometry, stroke, &options.poster, path, |_| ())?
+        }
+        ExportFormat::Pdf => write(path, geometry_pdf(geometry, stroke, &options.page))?,
+        ExportFormat::Eps => write(path, geometry_eps(geometry, stroke, &options.page))?,
+        ExportFormat::GCode | ExportFormat::Hpgl => {
+            let plan = PlotPlan::new(geometry, &options.page);
+            if format == ExportFormat::GCode {
+                write(path, plan.gcode(&options.plotter))?;
+            } else {
+                write(path, plan.hpgl())?;
+            }
+            summary = format!("{}: {}", summary, plan.stats(&options.plotter).summary());
+        }
+        ExportFormat::Dxf => write(path, geometry_dxf(geometry, &options.dxf))?,
+        ExportFormat::Obj | ExportFormat::Stl => {
+            let solids = TreeSolids::new(geometry, &options.solid);
+            if format == ExportFormat::Obj {
+                write(path, solids.obj())?;
+            } else {
+                write(path, solids.stl())?;
+            }
+            if !solids.is_watertight() {
+                summary = format!("{} (not watertight)", summary);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<(), ExportError> {
+    fs::write(path, contents).map_err(|e| ExportError::Io(e.to_string()))
+}
@@ -1,6 +1,10 @@
 use nannou::prelude::*;
 
-use crate::{DrawableLSystem, LSystemRules};
+use crate::{
+    color_palette::Palette, geometry::Geometry, jitter::Jitter, pruning::Pruning,
+    stroke::StrokeStyle, surfaces::SurfaceRegistry, DrawableLSystem, LSystemDrawingParamaters,
+    LSystemRules, StackPolicy, Tropism, TurtleError,
+};
 
 pub fn fractal_plant_rules_object() -> LSystemRules {
     let rules = vec![
@@ -11,13 +15,20 @@ pub fn fractal_plant_rules_object() -> LSystemRules {
     LSystemRules::new(vec!['X'], rules)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FractalPlantLSystem {
     pub line_length: f32,
     pub start_pos: Vec2,
     pub start_angle: f32,
     pub draw_color: Hsv,
     pub rules: LSystemRules,
+    pub palette: Palette,
+    pub tropism: Tropism,
+    pub jitter: Jitter,
+    pub stroke: StrokeStyle,
+    pub stack_policy: StackPolicy,
+    pub pruning: Pruning,
+    pub surfaces: SurfaceRegistry,
 }
 
 impl FractalPlantLSystem {
@@ -34,6 +45,13 @@ impl FractalPlantLSystem {
             start_angle,
             draw_color,
             rules,
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
+            stack_policy: StackPolicy::Error,
+            pruning: Pruning::default(),
+            surfaces: SurfaceRegistry::default(),
         }
     }
     pub fn default() -> Self {
@@ -43,6 +61,13 @@ impl FractalPlantLSystem {
             draw_color: hsv(0.3, 0.0, 1.0),
             start_angle: deg_to_rad(-30.0),
             rules: fractal_plant_rules_object(),
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
+            stack_policy: StackPolicy::Error,
+            pruning: Pruning::default(),
+            surfaces: SurfaceRegistry::default(),
         }
     }
     pub fn with_rules(rules: LSystemRules) -> Self {
@@ -52,55 +77,96 @@ impl FractalPlantLSystem {
             draw_color: hsv(0.3, 0.0, 1.0),
             start_angle: deg_to_rad(-30.0),
             rules,
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
+            stack_policy: StackPolicy::Error,
+            pruning: Pruning::default(),
+            surfaces: SurfaceRegistry::default(),
         }
     }
 }
 
 impl DrawableLSystem for FractalPlantLSystem {
-    fn draw(&self, draw: &Draw, _win: &Rect<f32>, levels: &usize) {
-        let evaluated_lsystem = self.rules.eval(levels).expect("lsystem evaluation failed");
+    fn geometry(&self, levels: &usize) -> Geometry {
+        let mut turtle = LSystemDrawingParamaters::new(self.start_pos, self.start_angle)
+            .with_tropism(self.tropism)
+            .with_jitter(self.jitter)
+            .with_stack_policy(self.stack_policy)
+            .with_pruning(self.pruning);
+        let mut geometry = Geometry::new();
+        let mut surface_pending = false;
 
-        let system_iter = evaluated_lsystem.chars();
-        let mut pos = self.start_pos;
-        let mut pos_stack: Vec<Vec2> = Vec::new();
-        pos_stack.push(pos);
-        let mut angle = self.start_angle;
-        let mut angle_stack: Vec<f32> = Vec::new();
-        angle_stack.push(angle);
-
-        for c in system_iter {
+        for (index, (c, generation)) in self.rules.eval_generations(levels).into_iter().enumerate()
+        {
+            turtle.set_symbol(index, generation);
+            if surface_pending {
+                surface_pending = false;
+                if turtle.is_pruned() {
+                    continue;
+                }
+                match self.surfaces.get(c) {
+                    Some(surface) => geometry.surface(
+                        surface,
+                        turtle.state.pos,
+                        turtle.state.angle,
+                        turtle.scaled(self.line_length),
+                        surface.color,
+                    ),
+                    None => geometry
+                        .diagnostics
+                        .push(TurtleError::UnknownSurface { index, name: c }),
+                }
+                continue;
+            }
             match c {
                 'F' => {
-                    let new_pos = pos + vec2(0.0, self.line_length).rotate(angle);
-                    draw.line()
-                        .start(pos)
-                        .end(new_pos)
-                        .color(self.draw_color)
-                        .stroke_weight(2.0);
-                    pos = new_pos;
+                    let (start, end) = turtle.forward(self.line_length);
+                    if turtle.is_pruned() {
+                        continue;
+                    }
+                    geometry.line(
+                        start,
+                        end,
+                        self.palette.color(&turtle.state, self.draw_color),
+                        2.0,
+                        turtle.segment_meta(),
+                    );
                 }
                 '-' => {
-                    angle += deg_to_rad(25.0);
+                    turtle.turn(deg_to_rad(25.0));
                 }
                 '+' => {
-                    angle -= deg_to_rad(25.0);
+                    turtle.turn(-deg_to_rad(25.0));
                 }
                 '[' => {
-                    pos_stack.push(pos);
-                    angle_stack.push(angle);
+                    turtle.push();
                 }
                 ']' => {
-                    pos = pos_stack.pop().unwrap();
-                    angle = angle_stack.pop().unwrap();
+                    if turtle.close_branch(&mut geometry.diagnostics).is_break() {
+                        break;
+                    }
+                }
+                '\'' => {
+                    turtle.next_color();
+                }
+                '~' => {
+                    surface_pending = true;
                 }
                 _ => (),
             }
         }
+
+        geometry
     }
 
     fn get_rules(&self) -> crate::LSystemRules {
         fractal_plant_rules_object()
     }
+    fn stroke_style(&self) -> &StrokeStyle {
+        &self.stroke
+    }
 }
 
 pub fn custom_fractal_plant_rules_object() -> LSystemRules {
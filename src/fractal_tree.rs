@@ -1,14 +1,32 @@
+use std::ops::ControlFlow;
+
 use nannou::prelude::*;
 
-use crate::{DrawableLSystem, LSystemRules};
+use crate::{
+    color_palette::Palette, geometry::Geometry, jitter::Jitter, pruning::Pruning,
+    stroke::StrokeStyle, surfaces::SurfaceRegistry, DrawableLSystem, LSystemDrawingParamaters,
+    LSystemRules, LengthScale, StackPolicy, Tropism, TurtleError,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FractalTreeLSystem {
     pub line_length: f32,
     pub start_pos: Vec2,
     pub start_angle: f32,
     pub branch_color: Hsv,
     pub leaf_color: Hsv,
+    /// Length of the stem drawn for every `0`.
+    pub leaf_stem_length: f32,
+    /// Length of the leaf surface placed at the end of every `0`.
+    pub leaf_size: f32,
+    pub length_scale: LengthScale,
+    pub palette: Palette,
+    pub tropism: Tropism,
+    pub jitter: Jitter,
+    pub stroke: StrokeStyle,
+    pub stack_policy: StackPolicy,
+    pub pruning: Pruning,
+    pub surfaces: SurfaceRegistry,
 }
 
 impl FractalTreeLSystem {
@@ -25,64 +43,122 @@ impl FractalTreeLSystem {
             start_angle,
             branch_color,
             leaf_color,
+            leaf_stem_length: 7.5,
+            leaf_size: 6.0,
+            length_scale: LengthScale::default(),
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
+            stack_policy: StackPolicy::Error,
+            pruning: Pruning::default(),
+            surfaces: SurfaceRegistry::default(),
         }
     }
 }
 
 impl DrawableLSystem for FractalTreeLSystem {
-    fn draw(&self, draw: &Draw, _win: &Rect<f32>, levels: &usize) {
-        let evaluated_lsystem = fractal_tree_rules_object()
-            .eval(levels)
-            .expect("fractal tree lsystem evaluation failed");
-
-        let system_iter = evaluated_lsystem.chars();
-        let mut pos = self.start_pos;
-        let mut pos_stack: Vec<Vec2> = Vec::new();
-        pos_stack.push(pos);
-        let mut angle = self.start_angle;
-        let mut angle_stack: Vec<f32> = Vec::new();
-        angle_stack.push(angle);
+    fn geometry(&self, levels: &usize) -> Geometry {
+        let mut turtle = LSystemDrawingParamaters::new(self.start_pos, self.start_angle)
+            .with_tropism(self.tropism)
+            .with_jitter(self.jitter)
+            .with_stack_policy(self.stack_policy)
+            .with_pruning(self.pruning)
+            .with_length_scale(self.length_scale);
+        let mut geometry = Geometry::new();
+        let mut surface_pending = false;
 
-        for c in system_iter {
+        for (index, (c, generation)) in fractal_tree_rules_object()
+            .eval_generations(levels)
+            .into_iter()
+            .enumerate()
+        {
+            turtle.set_symbol(index, generation);
+            if surface_pending {
+                surface_pending = false;
+                if turtle.is_pruned() {
+                    continue;
+                }
+                match self.surfaces.get(c) {
+                    Some(surface) => geometry.surface(
+                        surface,
+                        turtle.state.pos,
+                        turtle.state.angle,
+                        turtle.scaled(self.line_length),
+                        surface.color,
+                    ),
+                    None => geometry
+                        .diagnostics
+                        .push(TurtleError::UnknownSurface { index, name: c }),
+                }
+                continue;
+            }
             match c {
                 '1' => {
-                    let new_pos = pos + vec2(0.0, self.line_length).rotate(angle);
-                    draw.line()
-                        .start(pos)
-                        .end(new_pos)
-                        .color(self.branch_color)
-                        .stroke_weight(2.0);
-                    pos = dbg! { new_pos };
+                    let (start, end) = turtle.forward(self.line_length);
+                    if turtle.is_pruned() {
+                        continue;
+                    }
+                    geometry.line(
+                        start,
+                        end,
+                        self.palette.color(&turtle.state, self.branch_color),
+                        2.0,
+                        turtle.segment_meta(),
+                    );
                 }
+                '0' if turtle.is_pruned() => (),
                 '0' => {
-                    let new_pos = pos + vec2(0.0, 7.5).rotate(angle);
-                    draw.line()
-                        .start(pos)
-                        .end(new_pos)
-                        .color(self.branch_color)
-                        .stroke_weight(2.0);
-                    draw.ellipse()
-                        .x_y(new_pos.x, new_pos.y)
-                        .radius(3.0)
-                        .color(self.leaf_color);
+                    let pos = turtle.state.pos;
+                    let stem = vec2(0.0, turtle.scaled(self.leaf_stem_length));
+                    let new_pos = pos + stem.rotate(turtle.state.angle);
+                    geometry.line(
+                        pos,
+                        new_pos,
+                        self.palette.color(&turtle.state, self.branch_color),
+                        2.0,
+                        turtle.segment_meta(),
+                    );
+                    match self.surfaces.get('L') {
+                        Some(leaf) => geometry.surface(
+                            leaf,
+                            new_pos,
+                            turtle.state.angle,
+                            turtle.scaled(self.leaf_size),
+                            self.leaf_color,
+                        ),
+                        None => geometry
+                            .diagnostics
+                            .push(TurtleError::UnknownSurface { index, name: 'L' }),
+                    }
                 }
                 '[' => {
-                    pos_stack.push(pos);
-                    angle_stack.push(angle);
-                    angle += PI / 4.0;
+                    turtle.push();
+                    turtle.turn(PI / 4.0);
+                }
+                ']' => match turtle.close_branch(&mut geometry.diagnostics) {
+                    ControlFlow::Continue(true) => turtle.turn(-PI / 4.0),
+                    ControlFlow::Continue(false) => (),
+                    ControlFlow::Break(()) => break,
+                },
+                '\'' => {
+                    turtle.next_color();
                 }
-                ']' => {
-                    pos = pos_stack.pop().unwrap();
-                    angle = angle_stack.pop().unwrap();
-                    angle -= PI / 4.0;
+                '~' => {
+                    surface_pending = true;
                 }
                 _ => (),
             }
         }
+
+        geometry
     }
     fn get_rules(&self) -> LSystemRules {
         fractal_tree_rules_object()
     }
+    fn stroke_style(&self) -> &StrokeStyle {
+        &self.stroke
+    }
 }
 
 fn fractal_tree_rules_object() -> LSystemRules {
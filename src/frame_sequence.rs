@@ -0,0 +1,443 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use nannou::prelude::*;
+
+use crate::{
+    animation::frame_transform, export::ExportError, mesh::Mesh, raster::Raster, LSystemSelection,
+    Settings,
+};
+
+/// A setting animated across the frames of a sweep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SweepParameter {
+    /// Degrees.
+    StartAngle,
+    /// Degrees. Only the Koch and Levy C curves have a configurable turn angle.
+    TurnAngle,
+    /// Degrees around the color wheel, applied to the main draw color.
+    Hue,
+    LineLength,
+}
+
+impl SweepParameter {
+    pub const ALL: [SweepParameter; 4] = [
+        SweepParameter::StartAngle,
+        SweepParameter::TurnAngle,
+        SweepParameter::Hue,
+        SweepParameter::LineLength,
+    ];
+
+    /// Name used in sweep files.
+    pub fn key(&self) -> &'static str {
+        match self {
+            SweepParameter::StartAngle => "start_angle",
+            SweepParameter::TurnAngle => "turn_angle",
+            SweepParameter::Hue => "hue",
+            SweepParameter::LineLength => "line_length",
+        }
+    }
+    /// Sets the parameter of the selected system to `value`.
+    pub fn apply(&self, settings: &mut Settings, value: f32) -> Result<(), SweepError> {
+        let selection = settings.lsystem_selection;
+        let applied = match self {
+            SweepParameter::StartAngle => {
+                start_angle(settings).map(|angle| *angle = deg_to_rad(value))
+            }
+            SweepParameter::TurnAngle => match selection {
+                LSystemSelection::KochCurve => {
+                    settings.koch_curve_lsystem.turn_angle = deg_to_rad(value);
+                    Some(())
+                }
+                LSystemSelection::LevyCCurve => {
+                    settings.levy_c_curve_lsystem.turn_angle = deg_to_rad(value);
+                    Some(())
+                }
+                _ => None,
+            },
+            SweepParameter::Hue => draw_color(settings)
+                .map(|color| *color = Hsv::new(value, color.saturation, color.value)),
+            SweepParameter::LineLength => line_length(settings).map(|length| *length = value),
+        };
+        applied.ok_or(SweepError::Unsupported {
+            parameter: *self,
+            system: system_key(selection),
+        })
+    }
+}
+
+fn start_angle(settings: &mut Settings) -> Option<&mut f32> {
+    match settings.lsystem_selection {
+        LSystemSelection::DragonCurve => Some(&mut settings.dragon_curve_lsystem.start_angle),
+        LSystemSelection::SierpinskiTriangle => {
+            Some(&mut settings.sierpinski_triangle_lsystem.start_angle)
+        }
+        LSystemSelection::FractalTree => Some(&mut settings.fractal_tree_lsystem.start_angle),
+        LSystemSelection::FractalPlant => Some(&mut settings.fractal_plant_lsystem.start_angle),
+        LSystemSelection::KochCurve => Some(&mut settings.koch_curve_lsystem.start_angle),
+        LSystemSelection::LevyCCurve => Some(&mut settings.levy_c_curve_lsystem.start_angle),
+    }
+}
+
+fn line_length(settings: &mut Settings) -> Option<&mut f32> {
+    match settings.lsystem_selection {
+        LSystemSelection::DragonCurve => Some(&mut settings.dragon_curve_lsystem.line_length),
+        LSystemSelection::SierpinskiTriangle => {
+            Some(&mut settings.sierpinski_triangle_lsystem.line_length)
+        }
+        LSystemSelection::FractalTree => Some(&mut settings.fractal_tree_lsystem.line_length),
+        LSystemSelection::FractalPlant => Some(&mut settings.fractal_plant_lsystem.line_length),
+        LSystemSelection::KochCurve => Some(&mut settings.koch_curve_lsystem.line_length),
+        LSystemSelection::LevyCCurve => Some(&mut settings.levy_c_curve_lsystem.line_length),
+    }
+}
+
+fn draw_color(settings: &mut Settings) -> Option<&mut Hsv> {
+    match settings.lsystem_selection {
+        LSystemSelection::DragonCurve => Some(&mut settings.dragon_curve_lsystem.draw_color),
+        LSystemSelection::SierpinskiTriangle => {
+            Some(&mut settings.sierpinski_triangle_lsystem.draw_color)
+        }
+        LSystemSelection::FractalTree => Some(&mut settings.fractal_tree_lsystem.branch_color),
+        LSystemSelection::FractalPlant => Some(&mut settings.fractal_plant_lsystem.draw_color),
+        LSystemSelection::KochCurve => Some(&mut settings.koch_curve_lsystem.draw_color),
+        LSystemSelection::LevyCCurve => Some(&mut settings.levy_c_curve_lsystem.draw_color),
+    }
+}
+
+const SYSTEMS: [LSystemSelection; 6] = [
+    LSystemSelection::DragonCurve,
+    LSystemSelection::SierpinskiTriangle,
+    LSystemSelection::LevyCCurve,
+    LSystemSelection::FractalTree,
+    LSystemSelection::FractalPlant,
+    LSystemSelection::KochCurve,
+];
+
+fn system_key(selection: LSystemSelection) -> &'static str {
+    match selection {
+        LSystemSelection::DragonCurve => "dragon_curve",
+        LSystemSelection::SierpinskiTriangle => "sierpinski_triangle",
+        LSystemSelection::LevyCCurve => "levy_c_curve",
+        LSystemSelection::FractalTree => "fractal_tree",
+        LSystemSelection::FractalPlant => "fractal_plant",
+        LSystemSelection::KochCurve => "koch_curve",
+    }
+}
+
+/// A parameter animated linearly from `from` to `to` over `frames` numbered PNGs, written
+/// as `<output>/<prefix>_00000.png` and up so ffmpeg can pick them up with
+/// `-i <prefix>_%05d.png`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sweep {
+    pub parameter: SweepParameter,
+    pub from: f32,
+    pub to: f32,
+    pub frames: usize,
+    /// Overrides the system selected in the settings.
+    pub system: Option<LSystemSelection>,
+    /// Overrides the levels set in the settings.
+    pub levels: Option<usize>,
+    pub width: u32,
+    pub height: u32,
+    /// Anti-aliasing samples per pixel along each axis.
+    pub samples: u32,
+    /// Space kept around the drawing, in pixels.
+    pub margin: f32,
+    pub output: PathBuf,
+    pub prefix: String,
+}
+
+impl Sweep {
+    pub fn default() -> Self {
+        Sweep {
+            parameter: SweepParameter::StartAngle,
+            from: 0.0,
+            to: 360.0,
+            frames: 120,
+            system: None,
+            levels: None,
+            width: 1920,
+            height: 1080,
+            samples: 4,
+            margin: 20.0,
+            output: PathBuf::from("frames"),
+            prefix: "frame".to_string(),
+        }
+    }
+    /// Reads a sweep from `key = value` lines, with `#` starting a comment. Keys left out
+    /// keep their defaults, e.g.
+    ///
+    /// ```text
+    /// system = koch_curve
+    /// parameter = turn_angle
+    /// from = 60
+    /// to = 90
+    /// frames = 240
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, SweepError> {
+        let mut sweep = Sweep::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| SweepError::Parse {
+                line: index + 1,
+                message,
+            };
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected `key = value`, found `{}`", line)));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let number = || {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| error(format!("`{}` is not a number", value)))
+            };
+            let count = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| error(format!("`{}` is not a whole number", value)))
+            };
+            match key {
+                "parameter" => {
+                    sweep.parameter = SweepParameter::ALL
+                        .into_iter()
+                        .find(|parameter| parameter.key() == value)
+                        .ok_or_else(|| error(format!("unknown parameter `{}`", value)))?
+                }
+                "system" => {
+                    sweep.system = Some(
+                        SYSTEMS
+                            .into_iter()
+                            .find(|&system| system_key(system) == value)
+                            .ok_or_else(|| error(format!("unknown system `{}`", value)))?,
+                    )
+                }
+                "from" => sweep.from = number()?,
+                "to" => sweep.to = number()?,
+                "frames" => sweep.frames = count()? as usize,
+                "levels" => sweep.levels = Some(count()? as usize),
+                "width" => sweep.width = count()?,
+                "height" => sweep.height = count()?,
+                "samples" => sweep.samples = count()?,
+                "margin" => sweep.margin = number()?,
+                "output" => sweep.output = PathBuf::from(value),
+                "prefix" => sweep.prefix = value.to_string(),
+                _ => return Err(error(format!("unknown key `{}`", key))),
+            }
+        }
+        if sweep.frames == 0 || sweep.width == 0 || sweep.height == 0 {
+            return Err(SweepError::Invalid(
+                "frames, width and height must be at least 1".to_string(),
+            ));
+        }
+        Ok(sweep)
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SweepError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| SweepError::Export(ExportError::Io(e.to_string())))?;
+        Sweep::parse(&text)
+    }
+    /// Value of the parameter at `frame`, reaching `to` on the last frame.
+    pub fn value(&self, frame: usize) -> f32 {
+        match self.frames {
+            0 | 1 => self.from,
+            frames => self.from + (self.to - self.from) * frame as f32 / (frames - 1) as f32,
+        }
+    }
+    pub fn frame_path(&self, frame: usize) -> PathBuf {
+        self.output
+            .join(format!("{}_{:05}.png", self.prefix, frame))
+    }
+    /// `settings` with the overrides of the sweep and its parameter at `frame`.
+    pub fn frame_settings(
+        &self,
+        settings: &Settings,
+        frame: usize,
+    ) -> Result<Settings, SweepError> {
+        let mut settings = settings.clone();
+        if let Some(system) = self.system {
+            settings.lsystem_selection = system;
+        }
+        if let Some(levels) = self.levels {
+            settings.lsystem_levels = levels;
+        }
+        self.parameter.apply(&mut settings, self.value(frame))?;
+        Ok(settings)
+    }
+    /// Renders every frame starting from `settings` and describes the result. Every
+    /// frame's geometry is built before any is rendered, so all frames share one framing
+    /// and the drawing stays put while the parameter changes. `progress` is called with
+    /// the number of frames written so far.
+    pub fn export(
+        &self,
+        settings: &Settings,
+        mut progress: impl FnMut(usize),
+    ) -> Result<String, SweepError> {
+        let frames = (0..self.frames)
+            .map(|frame| {
+                let settings = self.frame_settings(settings, frame)?;
+                Ok((settings.geometry(settings.lsystem_levels), settings))
+            })
+            .collect::<Result<Vec<_>, SweepError>>()?;
+        let bounds = frames
+            .iter()
+            .filter_map(|(geometry, _)| geometry.bounds())
+            .collect::<Vec<_>>();
+        let transform = frame_transform(bounds, self.width, self.height, self.margin);
+
+        fs::create_dir_all(&self.output)
+            .map_err(|e| SweepError::Export(ExportError::Io(e.to_string())))?;
+        let raster = Raster::new(
+            self.width,
+            self.height,
+            Rect::from_w_h(self.width as f32, self.height as f32),
+        )
+        .with_samples(self.samples);
+        for (frame, (mut geometry, settings)) in frames.into_iter().enumerate() {
+            geometry.map_points(&transform);
+            raster
+                .render(&Mesh::from_geometry(
+                    &geometry,
+                    settings.selected_lsystem().stroke_style(),
+                ))
+                .save_png(self.frame_path(frame))
+                .map_err(SweepError::Export)?;
+            progress(frame + 1);
+        }
+        Ok(format!(
+            "wrote {} frames to {}",
+            self.frames,
+            self.output
+                .join(format!("{}_%05d.png", self.prefix))
+                .display()
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepError {
+    Parse {
+        line: usize,
+        message: String,
+    },
+    Invalid(String),
+    Unsupported {
+        parameter: SweepParameter,
+        system: &'static str,
+    },
+    Export(ExportError),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepError::Parse { line, message } => {
+                write!(f, "invalid sweep on line {}: {}", line, message)
+            }
+            SweepError::Invalid(message) => write!(f, "invalid sweep: {}", message),
+            SweepError::Unsupported { parameter, system } => {
+                write!(f, "{} has no {} to sweep", system, parameter.key())
+            }
+            SweepError::Export(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOCH_SWEEP: &str = "
+        # the example from the docs
+        system = koch_curve
+        parameter = turn_angle
+        from = 60
+        to = 90
+        frames = 240
+    ";
+
+    #[test]
+    fn parse_documented_example() {
+        let sweep = Sweep::parse(KOCH_SWEEP).unwrap();
+        assert_eq!(sweep.system, Some(LSystemSelection::KochCurve));
+        assert_eq!(sweep.parameter, SweepParameter::TurnAngle);
+        assert_eq!((sweep.from, sweep.to, sweep.frames), (60.0, 90.0, 240));
+        assert_eq!(sweep.width, Sweep::default().width);
+        assert_eq!(sweep.value(0), 60.0);
+        assert_eq!(sweep.value(239), 90.0);
+        assert_eq!(
+            sweep.frame_path(7),
+            PathBuf::from("frames").join("frame_00007.png")
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = Sweep::parse("frames = 10\nspeed = 3").unwrap_err();
+        assert!(matches!(error, SweepError::Parse { line: 2, .. }));
+        let error = Sweep::parse("from = fast").unwrap_err();
+        assert!(matches!(error, SweepError::Parse { line: 1, .. }));
+        assert!(matches!(
+            Sweep::parse("frames = 0"),
+            Err(SweepError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn turn_angle_changes_the_koch_curve() {
+        let sweep = Sweep {
+            levels: Some(1),
+            frames: 3,
+            ..Sweep::parse(KOCH_SWEEP).unwrap()
+        };
+        let geometry = |frame| {
+            let settings = sweep.frame_settings(&Settings::default(), frame).unwrap();
+            settings.geometry(settings.lsystem_levels)
+        };
+        let (first, last) = (geometry(0), geometry(2));
+        assert_eq!(first.segment_count(), last.segment_count());
+        assert_ne!(first, last);
+
+        // at 90 degrees every segment is axis aligned
+        for (a, b, _) in last.segments() {
+            let d = b - a;
+            assert!(d.x.abs() < 1e-3 || d.y.abs() < 1e-3, "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn every_parameter_sweeps_the_levy_c_curve() {
+        for parameter in SweepParameter::ALL {
+            let sweep = Sweep {
+                system: Some(LSystemSelection::LevyCCurve),
+                levels: Some(3),
+                parameter,
+                from: 10.0,
+                to: 30.0,
+                frames: 2,
+                ..Sweep::default()
+            };
+            let geometry = |frame| {
+                let settings = sweep.frame_settings(&Settings::default(), frame).unwrap();
+                settings.geometry(settings.lsystem_levels)
+            };
+            assert_ne!(geometry(0), geometry(1), "{:?}", parameter);
+        }
+    }
+
+    #[test]
+    fn unsupported_parameters_are_reported() {
+        let sweep = Sweep {
+            system: Some(LSystemSelection::DragonCurve),
+            parameter: SweepParameter::TurnAngle,
+            ..Sweep::default()
+        };
+        let error = sweep.frame_settings(&Settings::default(), 0).unwrap_err();
+        assert_eq!(error.to_string(), "dragon_curve has no turn_angle to sweep");
+    }
+}
@@ -0,0 +1,325 @@
+use nannou::prelude::*;
+
+use crate::{
+    lsystems::{SegmentMeta, TurtleError},
+    surfaces::Surface,
+};
+
+/// A connected run of turtle steps drawn as one stroke.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<Vec2>,
+    /// One color per point. Each point after the first takes the color of the segment
+    /// ending at it, so color changes blend across a single segment.
+    pub colors: Vec<Hsv>,
+    pub width: f32,
+    /// One entry per segment, so `segments[i]` describes `points[i]..points[i + 1]`.
+    pub segments: Vec<SegmentMeta>,
+}
+
+impl Polyline {
+    /// Splits the polyline wherever the color changes, for outputs that can only stroke a
+    /// path in one color. Each run takes the color of its segments.
+    pub fn color_runs(&self) -> Vec<(Hsv, Vec<Vec2>)> {
+        let mut runs: Vec<(Hsv, Vec<Vec2>)> = Vec::new();
+        for (i, pair) in self.points.windows(2).enumerate() {
+            let color = self.colors[i + 1];
+            match runs.last_mut() {
+                Some((run_color, points)) if *run_color == color => points.push(pair[1]),
+                _ => runs.push((color, pair.to_vec())),
+            }
+        }
+        runs
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub points: Vec<Vec2>,
+    pub color: Hsv,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub pos: Vec2,
+    pub radius: f32,
+    pub color: Hsv,
+}
+
+/// What a turtle produced from an evaluated lsystem, independent of how it ends up rendered.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Geometry {
+    pub polylines: Vec<Polyline>,
+    pub polygons: Vec<Polygon>,
+    pub points: Vec<Point>,
+    /// Problems the turtle ran into, for the UI to show.
+    pub diagnostics: Vec<TurtleError>,
+}
+
+impl Geometry {
+    pub fn new() -> Self {
+        Geometry::default()
+    }
+    /// Adds a segment, continuing the last polyline if the segment starts where it ended
+    /// with the same width. Color changes don't break the run, so a connected stroke is
+    /// drawn as one path however it is colored.
+    pub fn line(&mut self, start: Vec2, end: Vec2, color: Hsv, width: f32, meta: SegmentMeta) {
+        if let Some(last) = self.polylines.last_mut() {
+            if last.points.last() == Some(&start) && last.width == width {
+                last.points.push(end);
+                last.colors.push(color);
+                last.segments.push(meta);
+                return;
+            }
+        }
+        self.polylines.push(Polyline {
+            points: vec![start, end],
+            colors: vec![color, color],
+            width,
+            segments: vec![meta],
+        });
+    }
+    pub fn polygon(&mut self, points: Vec<Vec2>, color: Hsv) {
+        self.polygons.push(Polygon { points, color });
+    }
+    /// Instantiates `surface` at `pos`, turned to `angle` and scaled by `scale`.
+    pub fn surface(&mut self, surface: &Surface, pos: Vec2, angle: f32, scale: f32, color: Hsv) {
+        for points in surface.place(pos, angle, scale) {
+            self.polygon(points, color);
+        }
+    }
+    pub fn point(&mut self, pos: Vec2, radius: f32, color: Hsv) {
+        self.points.push(Point { pos, radius, color });
+    }
+    /// Every segment with the metadata it was emitted with.
+    pub fn segments(&self) -> impl Iterator<Item = (Vec2, Vec2, &SegmentMeta)> {
+        self.polylines.iter().flat_map(|polyline| {
+            polyline
+                .points
+                .windows(2)
+                .zip(&polyline.segments)
+                .map(|(pair, meta)| (pair[0], pair[1], meta))
+        })
+    }
+    pub fn segment_count(&self) -> usize {
+        self.segments().count()
+    }
+    /// Smallest rect containing every vertex, polygon and point.
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut points = self
+            .polylines
+            .iter()
+            .flat_map(|polyline| polyline.points.iter().copied())
+            .chain(
+                self.polygons
+                    .iter()
+                    .flat_map(|polygon| polygon.points.iter().copied()),
+            )
+            .chain(self.points.iter().flat_map(|point| {
+                let r = vec2(point.radius, point.radius);
+                [point.pos - r, point.pos + r]
+            }));
+
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+        Some(Rect {
+            x: Range::new(min.x, max.x),
+            y: Range::new(min.y, max.y),
+        })
+    }
+    /// Applies `f` to every vertex and point position.
+    pub fn map_points(&mut self, f: impl Fn(Vec2) -> Vec2) {
+        for polyline in &mut self.polylines {
+            polyline.points.iter_mut().for_each(|p| *p = f(*p));
+        }
+        for polygon in &mut self.polygons {
+            polygon.points.iter_mut().for_each(|p| *p = f(*p));
+        }
+        for point in &mut self.points {
+            point.pos = f(point.pos);
+        }
+    }
+    /// Multiplies stroke widths and point radii by `factor`.
+    pub fn scale_widths(&mut self, factor: f32) {
+        for polyline in &mut self.polylines {
+            polyline.width *= factor;
+        }
+        for point in &mut self.points {
+            point.radius *= factor;
+        }
+    }
+    /// Applies `f` to the color of every polyline, polygon and point.
+    pub fn map_colors(&mut self, f: impl Fn(Hsv) -> Hsv) {
+        for polyline in &mut self.polylines {
+            polyline.colors.iter_mut().for_each(|c| *c = f(*c));
+        }
+        for polygon in &mut self.polygons {
+            polygon.color = f(polygon.color);
+        }
+        for point in &mut self.points {
+            point.color = f(point.color);
+        }
+    }
+    /// Moves the shapes of `other` into this geometry, drawn on top. Diagnostics of
+    /// `other` are dropped.
+    pub fn append(&mut self, other: Geometry) {
+        self.polylines.extend(other.polylines);
+        self.polygons.extend(other.polygons);
+        self.points.extend(other.points);
+    }
+    /// The first `fraction` of the segments in the order they were drawn, for growth
+    /// animations. Polygons and points appear in proportion.
+    pub fn grown(&self, fraction: f32) -> Geometry {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let portion = |count: usize| (count as f32 * fraction).ceil() as usize;
+        let mut remaining = portion(self.segment_count());
+        let mut geometry = Geometry::new();
+        for polyline in &self.polylines {
+            if remaining == 0 {
+                break;
+            }
+            let count = polyline.segments.len().min(remaining);
+            remaining -= count;
+            geometry.polylines.push(Polyline {
+                points: polyline.points[..=count].to_vec(),
+                colors: polyline.colors[..=count].to_vec(),
+                width: polyline.width,
+                segments: polyline.segments[..count].to_vec(),
+            });
+        }
+        geometry.polygons = self.polygons[..portion(self.polygons.len())].to_vec();
+        geometry.points = self.points[..portion(self.points.len())].to_vec();
+        geometry
+    }
+    /// Uniformly scales and centers the geometry so its bounds fill `target`.
+    /// Stroke widths and point radii stay in screen units.
+    pub fn fit_to(&mut self, target: Rect) {
+        let Some(bounds) = self.bounds() else {
+            return;
+        };
+        if target.w() <= 0.0 || target.h() <= 0.0 {
+            return;
+        }
+        let scale = match (bounds.w() > 0.0, bounds.h() > 0.0) {
+            (true, true) => (target.w() / bounds.w()).min(target.h() / bounds.h()),
+            (true, false) => target.w() / bounds.w(),
+            (false, true) => target.h() / bounds.h(),
+            (false, false) => 1.0,
+        };
+        let from = bounds.xy();
+        let to = target.xy();
+
+        self.map_points(|p| (p - from) * scale + to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(index: usize) -> SegmentMeta {
+        SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index,
+            arc_length: 0.0,
+            branch_id: 0,
+        }
+    }
+
+    /// Four unit segments forming a square, then a disconnected segment.
+    fn square() -> Geometry {
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        let corners = [
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(0.0, 1.0),
+        ];
+        let mut geometry = Geometry::new();
+        for i in 0..4 {
+            geometry.line(corners[i], corners[(i + 1) % 4], color, 2.0, meta(i));
+        }
+        geometry.line(vec2(3.0, 0.0), vec2(3.0, 2.0), color, 2.0, meta(4));
+        geometry
+    }
+
+    #[test]
+    fn line_continues_connected_segments() {
+        let geometry = square();
+        assert_eq!(geometry.polylines.len(), 2);
+        assert_eq!(geometry.polylines[0].points.len(), 5);
+        assert_eq!(geometry.segment_count(), 5);
+    }
+
+    #[test]
+    fn line_breaks_on_width_change() {
+        let mut geometry = Geometry::new();
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        geometry.line(vec2(0.0, 0.0), vec2(1.0, 0.0), color, 2.0, meta(0));
+        geometry.line(vec2(1.0, 0.0), vec2(2.0, 0.0), color, 4.0, meta(1));
+        assert_eq!(geometry.polylines.len(), 2);
+    }
+
+    #[test]
+    fn bounds_cover_every_shape() {
+        assert_eq!(Geometry::new().bounds(), None);
+
+        let mut geometry = square();
+        let bounds = geometry.bounds().unwrap();
+        assert_eq!((bounds.left(), bounds.right()), (0.0, 3.0));
+        assert_eq!((bounds.bottom(), bounds.top()), (0.0, 2.0));
+
+        geometry.point(vec2(-1.0, 0.0), 0.5, Hsv::new(0.0, 1.0, 1.0));
+        geometry.polygon(
+            vec![vec2(0.0, 0.0), vec2(0.0, 5.0), vec2(1.0, 5.0)],
+            Hsv::new(0.0, 1.0, 1.0),
+        );
+        let bounds = geometry.bounds().unwrap();
+        assert_eq!((bounds.left(), bounds.right()), (-1.5, 3.0));
+        assert_eq!((bounds.bottom(), bounds.top()), (-0.5, 5.0));
+    }
+
+    #[test]
+    fn grown_truncates_in_drawing_order() {
+        let geometry = square();
+        assert_eq!(geometry.grown(0.0), Geometry::new());
+        assert_eq!(
+            geometry.grown(1.0),
+            Geometry {
+                diagnostics: Vec::new(),
+                ..geometry.clone()
+            }
+        );
+
+        let half = geometry.grown(0.5);
+        // ceil(5 * 0.5) segments, all from the first polyline
+        assert_eq!(half.segment_count(), 3);
+        assert_eq!(half.polylines.len(), 1);
+        assert_eq!(half.polylines[0].points, geometry.polylines[0].points[..4]);
+        assert_eq!(half.polylines[0].colors.len(), 4);
+        let indices: Vec<usize> = half.segments().map(|(_, _, meta)| meta.index).collect();
+        assert_eq!(indices, [0, 1, 2]);
+
+        // the last segment starts the second polyline
+        let most = geometry.grown(0.9);
+        assert_eq!(most.segment_count(), 5);
+        assert_eq!(most.polylines.len(), 2);
+    }
+
+    #[test]
+    fn grown_clamps_fraction() {
+        let geometry = square();
+        assert_eq!(geometry.grown(-1.0), geometry.grown(0.0));
+        assert_eq!(geometry.grown(2.0), geometry.grown(1.0));
+    }
+
+    #[test]
+    fn fit_to_keeps_aspect_ratio() {
+        let mut geometry = square();
+        geometry.fit_to(Rect::from_w_h(30.0, 30.0));
+        let bounds = geometry.bounds().unwrap();
+        assert_eq!(bounds.xy(), vec2(0.0, 0.0));
+        assert_eq!(bounds.wh(), vec2(30.0, 20.0));
+    }
+}
@@ -0,0 +1,161 @@
+use nannou::prelude::*;
+
+/// SplitMix64, so a stored seed reproduces the same render on every machine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JitterRng {
+    state: u64,
+}
+
+impl JitterRng {
+    pub fn new(seed: u64) -> Self {
+        JitterRng { state: seed }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseDistribution {
+    Uniform,
+    Gaussian,
+}
+
+impl NoiseDistribution {
+    pub const ALL: [NoiseDistribution; 2] =
+        [NoiseDistribution::Uniform, NoiseDistribution::Gaussian];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NoiseDistribution::Uniform => "Uniform",
+            NoiseDistribution::Gaussian => "Gaussian",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub distribution: NoiseDistribution,
+    /// Half-width for uniform noise, standard deviation for gaussian noise.
+    pub amount: f32,
+}
+
+impl Noise {
+    pub fn new(distribution: NoiseDistribution, amount: f32) -> Self {
+        Noise {
+            distribution,
+            amount,
+        }
+    }
+    pub fn sample(&self, rng: &mut JitterRng) -> f32 {
+        if self.amount == 0.0 {
+            return 0.0;
+        }
+        match self.distribution {
+            NoiseDistribution::Uniform => (rng.next_f32() * 2.0 - 1.0) * self.amount,
+            NoiseDistribution::Gaussian => {
+                // Box-Muller, nudged away from ln(0)
+                let u1 = rng.next_f32().max(f32::EPSILON);
+                let u2 = rng.next_f32();
+                (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos() * self.amount
+            }
+        }
+    }
+}
+
+/// Per-step variation applied while the turtle interprets a system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Jitter {
+    pub seed: u64,
+    /// Added to every turn, in radians.
+    pub angle: Noise,
+    /// Relative change of every step length.
+    pub length: Noise,
+}
+
+impl Jitter {
+    pub fn new(seed: u64, angle: Noise, length: Noise) -> Self {
+        Jitter {
+            seed,
+            angle,
+            length,
+        }
+    }
+    pub fn default() -> Self {
+        Jitter::new(
+            0,
+            Noise::new(NoiseDistribution::Uniform, 0.0),
+            Noise::new(NoiseDistribution::Uniform, 0.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{levy_c_curve::LevyCCurve, DrawableLSystem};
+
+    fn samples(noise: Noise, seed: u64, count: usize) -> Vec<f32> {
+        let mut rng = JitterRng::new(seed);
+        (0..count).map(|_| noise.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let noise = Noise::new(NoiseDistribution::Gaussian, 1.0);
+        assert_eq!(samples(noise, 7, 100), samples(noise, 7, 100));
+        assert_ne!(samples(noise, 7, 100), samples(noise, 8, 100));
+    }
+
+    #[test]
+    fn uniform_noise_stays_within_amount() {
+        let values = samples(Noise::new(NoiseDistribution::Uniform, 0.5), 1, 10_000);
+        assert!(values.iter().all(|value| value.abs() <= 0.5));
+        // and actually spreads across the range
+        assert!(values.iter().any(|&value| value > 0.45));
+        assert!(values.iter().any(|&value| value < -0.45));
+    }
+
+    #[test]
+    fn gaussian_noise_has_its_mean_and_spread() {
+        let values = samples(Noise::new(NoiseDistribution::Gaussian, 2.0), 3, 20_000);
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>()
+            / values.len() as f32;
+        assert!(mean.abs() < 0.05, "{}", mean);
+        assert!((variance.sqrt() - 2.0).abs() < 0.05, "{}", variance.sqrt());
+    }
+
+    #[test]
+    fn zero_amount_is_silent() {
+        let values = samples(Noise::new(NoiseDistribution::Gaussian, 0.0), 3, 10);
+        assert!(values.iter().all(|&value| value == 0.0));
+    }
+
+    #[test]
+    fn one_seed_builds_one_geometry() {
+        let jittered = |seed| {
+            let mut levy = LevyCCurve::default();
+            levy.jitter = Jitter::new(
+                seed,
+                Noise::new(NoiseDistribution::Gaussian, 0.05),
+                Noise::new(NoiseDistribution::Uniform, 0.2),
+            );
+            levy.geometry(&6)
+        };
+        assert_eq!(jittered(42), jittered(42));
+        assert_ne!(jittered(42), jittered(43));
+        assert_ne!(jittered(42), LevyCCurve::default().geometry(&6));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Lets a job report how many of its steps are done.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    done: Arc<AtomicUsize>,
+}
+
+impl Progress {
+    pub fn set(&self, done: usize) {
+        self.done.store(done, Ordering::Relaxed);
+    }
+}
+
+/// An export running on a background thread, so the window keeps drawing while it runs.
+pub struct Job {
+    progress: Progress,
+    steps: usize,
+    thread: Option<JoinHandle<Result<String, String>>>,
+}
+
+impl Job {
+    /// Runs `work` on its own thread. It reports progress out of `steps` and describes
+    /// its result.
+    pub fn spawn<E: fmt::Display>(
+        steps: usize,
+        work: impl FnOnce(Progress) -> Result<String, E> + Send + 'static,
+    ) -> Self {
+        let progress = Progress {
+            done: Arc::new(AtomicUsize::new(0)),
+        };
+        let reporter = progress.clone();
+        let thread = thread::spawn(move || work(reporter).map_err(|error| error.to_string()));
+        Job {
+            progress,
+            steps: steps.max(1),
+            thread: Some(thread),
+        }
+    }
+    /// Fraction of the steps done so far.
+    pub fn progress(&self) -> f32 {
+        let done = self.progress.done.load(Ordering::Relaxed).min(self.steps);
+        done as f32 / self.steps as f32
+    }
+    /// The outcome once the work has finished, `None` while it is still running.
+    pub fn poll(&mut self) -> Option<Result<String, String>> {
+        if !self.thread.as_ref()?.is_finished() {
+            return None;
+        }
+        let result = self.thread.take()?.join();
+        Some(result.unwrap_or_else(|_| Err("export failed unexpectedly".to_string())))
+    }
+}
+
+/// Takes the outcome of a finished job as a status line, and returns how far a job still
+/// running has got.
+pub fn poll_job(job: &mut Option<Job>, status: &mut Option<String>) -> Option<f32> {
+    if let Some(result) = job.as_mut().and_then(Job::poll) {
+        *status = Some(match result {
+            Ok(summary) => summary,
+            Err(error) => error,
+        });
+        *job = None;
+    }
+    job.as_ref().map(Job::progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(job: &mut Option<Job>, status: &mut Option<String>) {
+        while poll_job(job, status).is_some() {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn finished_job_becomes_the_status() {
+        let mut job = Some(Job::spawn(4, |progress| {
+            for step in 1..=4 {
+                progress.set(step);
+            }
+            Ok::<_, String>("done".to_string())
+        }));
+        let mut status = None;
+        wait(&mut job, &mut status);
+        assert!(job.is_none());
+        assert_eq!(status.as_deref(), Some("done"));
+    }
+
+    #[test]
+    fn errors_and_panics_are_reported() {
+        let mut job = Some(Job::spawn(1, |_| Err("no room".to_string())));
+        let mut status = None;
+        wait(&mut job, &mut status);
+        assert_eq!(status.as_deref(), Some("no room"));
+
+        let mut job = Some(Job::spawn(1, |_| -> Result<String, String> {
+            panic!("boom")
+        }));
+        wait(&mut job, &mut status);
+        assert_eq!(status.as_deref(), Some("export failed unexpectedly"));
+    }
+
+    #[test]
+    fn progress_is_a_fraction_of_the_steps() {
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let mut job = Job::spawn(4, move |progress| {
+            progress.set(3);
+            receiver.recv().ok();
+            Ok::<_, String>(String::new())
+        });
+        while job.progress() < 0.75 {
+            thread::yield_now();
+        }
+        assert_eq!(job.progress(), 0.75);
+        assert!(job.poll().is_none());
+        sender.send(()).unwrap();
+        while job.poll().is_none() {
+            thread::yield_now();
+        }
+    }
+}
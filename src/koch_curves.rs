@@ -1,8 +1,11 @@
 use nannou::prelude::*;
 
-use crate::{DrawableLSystem, LSystemRules};
+use crate::{
+    color_palette::Palette, geometry::Geometry, jitter::Jitter, stroke::StrokeStyle,
+    DrawableLSystem, LSystemDrawingParamaters, LSystemRules, Tropism,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct KochCurveLSystem {
     pub line_length: f32,
     pub start_pos: Vec2,
@@ -10,6 +13,10 @@ pub struct KochCurveLSystem {
     pub turn_angle: f32,
     pub rules: LSystemRules,
     pub draw_color: Hsv,
+    pub palette: Palette,
+    pub tropism: Tropism,
+    pub jitter: Jitter,
+    pub stroke: StrokeStyle,
 }
 
 impl KochCurveLSystem {
@@ -21,6 +28,10 @@ impl KochCurveLSystem {
             turn_angle,
             rules: koch_island_rules_object(),
             draw_color: Hsv::new(0.0, 0.0, 1.0),
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
         }
     }
     pub fn default() -> Self {
@@ -31,6 +42,10 @@ impl KochCurveLSystem {
             turn_angle: deg_to_rad(90.0),
             rules: koch_island_rules_object(),
             draw_color: Hsv::new(0.0, 0.0, 1.0),
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
         }
     }
     pub fn with_rules(rules: LSystemRules) -> Self {
@@ -41,45 +56,59 @@ impl KochCurveLSystem {
             turn_angle: deg_to_rad(90.0),
             rules,
             draw_color: Hsv::new(0.0, 0.0, 1.0),
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
         }
     }
 }
 
 impl DrawableLSystem for KochCurveLSystem {
-    fn draw(&self, draw: &Draw, win: &Rect<f32>, levels: &usize) {
-        let evaluated_lsystem = self.rules.eval(levels).expect("lsystem evaluation failed");
+    fn geometry(&self, levels: &usize) -> Geometry {
+        let mut turtle = LSystemDrawingParamaters::new(self.start_pos, self.start_angle)
+            .with_tropism(self.tropism)
+            .with_jitter(self.jitter);
+        let mut geometry = Geometry::new();
 
-        let system_iter = evaluated_lsystem.chars();
-        let mut pos = self.start_pos;
-        let mut angle = self.start_angle;
-
-        for c in system_iter {
+        for (index, (c, generation)) in self.rules.eval_generations(levels).into_iter().enumerate()
+        {
+            turtle.set_symbol(index, generation);
             match c {
                 'F' => {
-                    let new_pos = pos + vec2(0.0, self.line_length).rotate(angle);
-                    draw.line()
-                        .start(pos)
-                        .end(new_pos)
-                        .color(self.draw_color)
-                        .stroke_weight(2.0);
-                    pos = new_pos;
+                    let (start, end) = turtle.forward(self.line_length);
+                    geometry.line(
+                        start,
+                        end,
+                        self.palette.color(&turtle.state, self.draw_color),
+                        2.0,
+                        turtle.segment_meta(),
+                    );
                 }
                 'f' => {
-                    pos += vec2(0.0, self.line_length).rotate(angle);
+                    turtle.forward(self.line_length);
                 }
                 '+' => {
-                    angle += PI / 2.0;
+                    turtle.turn(self.turn_angle);
                 }
                 '-' => {
-                    angle -= PI / 2.0;
+                    turtle.turn(-self.turn_angle);
+                }
+                '\'' => {
+                    turtle.next_color();
                 }
                 _ => (),
             }
         }
+
+        geometry
     }
     fn get_rules(&self) -> LSystemRules {
         self.rules.clone()
     }
+    fn stroke_style(&self) -> &StrokeStyle {
+        &self.stroke
+    }
 }
 
 pub fn koch_island_rules_object() -> LSystemRules {
@@ -1,4 +1,7 @@
-use crate::{DrawableLSystem, LSystemDrawingParamaters, LSystemRules};
+use crate::{
+    color_palette::Palette, geometry::Geometry, jitter::Jitter, stroke::StrokeStyle,
+    DrawableLSystem, LSystemDrawingParamaters, LSystemRules, Tropism,
+};
 
 use nannou::prelude::*;
 
@@ -20,25 +23,44 @@ pub fn setup_levy_c_curve_lsystem(rect: Rect) -> LevyCCurve {
     let drawable = LevyCCurve::new(axiom, rules, start_pos);
     drawable
 }
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LevyCCurve {
-    params: LSystemDrawingParamaters,
+    pub line_length: f32,
+    pub start_pos: Vec2,
+    pub start_angle: f32,
+    pub turn_angle: f32,
+    pub draw_color: Hsv,
+    pub palette: Palette,
+    pub tropism: Tropism,
+    pub jitter: Jitter,
+    pub stroke: StrokeStyle,
 }
 
 impl LevyCCurve {
     pub fn new(_axiom: Vec<char>, _rules: Vec<(char, String)>, start_pos: Vec2) -> Self {
-        let start_pos = start_pos;
-        let start_angle = deg_to_rad(90.0);
-        let params = LSystemDrawingParamaters::new(start_pos, start_angle);
-
-        LevyCCurve { params }
+        LevyCCurve {
+            line_length: 3.0,
+            start_pos,
+            start_angle: deg_to_rad(90.0),
+            turn_angle: deg_to_rad(45.0),
+            draw_color: Hsv::from(BLUE.into_format::<f32>()),
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
+        }
     }
 
     pub fn with_params(
-        _axiom: Vec<char>,
-        _rules: Vec<(char, String)>,
+        axiom: Vec<char>,
+        rules: Vec<(char, String)>,
         params: LSystemDrawingParamaters,
     ) -> Self {
-        LevyCCurve { params }
+        LevyCCurve {
+            start_angle: params.start_angle,
+            ..LevyCCurve::new(axiom, rules, params.start_pos)
+        }
     }
 
     pub fn default() -> Self {
@@ -49,36 +71,70 @@ impl LevyCCurve {
 }
 
 impl DrawableLSystem for LevyCCurve {
-    fn draw(&self, draw: &Draw, _win: &Rect<f32>, levels: &usize) {
-        let evaluated_lsystem = levy_rules_object()
-            .eval(levels)
-            .expect("lsystem evaluation failed");
-        let system_iter = evaluated_lsystem.chars();
-        let mut pos = self.params.start_pos;
-        let mut angle = self.params.angle;
-        for c in system_iter {
+    fn geometry(&self, levels: &usize) -> Geometry {
+        let mut turtle = LSystemDrawingParamaters::new(self.start_pos, self.start_angle)
+            .with_tropism(self.tropism)
+            .with_jitter(self.jitter);
+        let mut geometry = Geometry::new();
+
+        for (index, (c, generation)) in levy_rules_object()
+            .eval_generations(levels)
+            .into_iter()
+            .enumerate()
+        {
+            turtle.set_symbol(index, generation);
             match c {
                 'F' => {
-                    let new_pos = pos + vec2(0.0, 3.0).rotate(angle);
-                    draw.line()
-                        .start(pos)
-                        .end(new_pos)
-                        .color(BLUE)
-                        .stroke_weight(1.0);
-                    pos = new_pos;
+                    let (start, end) = turtle.forward(self.line_length);
+                    geometry.line(
+                        start,
+                        end,
+                        self.palette.color(&turtle.state, self.draw_color),
+                        1.0,
+                        turtle.segment_meta(),
+                    );
                 }
                 '+' => {
-                    angle += deg_to_rad(45.0);
+                    turtle.turn(self.turn_angle);
                 }
                 '-' => {
-                    angle -= deg_to_rad(45.0);
+                    turtle.turn(-self.turn_angle);
+                }
+                '\'' => {
+                    turtle.next_color();
                 }
                 _ => (),
             }
         }
+
+        geometry
     }
 
     fn get_rules(&self) -> LSystemRules {
         levy_rules_object()
     }
+    fn stroke_style(&self) -> &StrokeStyle {
+        &self.stroke
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_palette::{ColorSource, Gradient};
+
+    #[test]
+    fn palette_colors_the_curve() {
+        let mut levy = LevyCCurve::default();
+        levy.palette = Palette::new(
+            ColorSource::Distance,
+            Gradient::new(vec![(0.0, hsv(0.0, 0.0, 0.0)), (1.0, hsv(0.0, 0.0, 1.0))]),
+            0.01,
+            false,
+        );
+        let geometry = levy.geometry(&4);
+        let colors = &geometry.polylines[0].colors;
+        assert!(colors[1].value < colors[colors.len() - 1].value);
+        assert!(colors.iter().all(|color| color.saturation == 0.0));
+    }
 }
@@ -1,9 +1,16 @@
 use nannou::prelude::*;
 use nannou_egui::{self, egui, Egui};
 
-use crate::LSystemRules;
+use crate::{
+    animation::{AnimationFormat, AnimationSettings, Growth},
+    dxf_export::{DxfLayering, DxfSettings, DxfUnits},
+    export::{ExportFormat, ExportOptions},
+    pdf_export::{PageLayout, PageSize},
+    surfaces::{Surface, SurfaceRegistry},
+    LSystemRules,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LSystemRulesEditor {
     rules: LSystemRules,
 }
@@ -39,3 +46,327 @@ impl LSystemRulesEditor {
         });
     }
 }
+
+/// Lists the `~X` surfaces of a system and loads new ones from SVG files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceEditor {
+    name: String,
+    path: String,
+    status: Option<String>,
+}
+impl SurfaceEditor {
+    pub fn new() -> Self {
+        Self {
+            name: "S".to_string(),
+            path: String::new(),
+            status: None,
+        }
+    }
+    pub fn show(&mut self, ui: &mut egui::Ui, surfaces: &mut SurfaceRegistry) {
+        ui.label("Surfaces (~X)");
+        for (name, surface) in surfaces.surfaces.iter_mut() {
+            ui.horizontal(|ui| {
+                ui.label(name.to_string());
+                crate::egui_edit_hsv(ui, &mut surface.color);
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Symbol");
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(16.0));
+            ui.label("SVG");
+            ui.text_edit_singleline(&mut self.path);
+        });
+        if ui.button("Load SVG").clicked() {
+            self.status = Some(match self.name.chars().next() {
+                None => "enter a symbol to load the surface as".to_string(),
+                Some(name) => match Surface::from_svg_file(&self.path, hsv(0.0, 0.0, 1.0)) {
+                    Ok(surface) => {
+                        surfaces.insert(name, surface);
+                        format!("loaded '{}' from {}", name, self.path)
+                    }
+                    Err(error) => error.to_string(),
+                },
+            });
+        }
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+    }
+}
+
+/// Picks a format and file for exporting the current drawing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportPanel {
+    pub format: ExportFormat,
+    pub options: ExportOptions,
+    pub path: String,
+    pub status: Option<String>,
+    /// How far an export rendering in the background has got, while one is running.
+    pub progress: Option<f32>,
+}
+impl ExportPanel {
+    pub fn new() -> Self {
+        Self {
+            format: ExportFormat::Svg,
+            options: ExportOptions::default(),
+            path: format!("lsystem.{}", ExportFormat::Svg.extension()),
+            status: None,
+            progress: None,
+        }
+    }
+    /// Returns true when the export button was clicked.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.label("Export");
+        let previous = self.format;
+        egui::ComboBox::from_label("Format")
+            .selected_text(self.format.label())
+            .show_ui(ui, |ui| {
+                for format in ExportFormat::ALL {
+                    ui.selectable_value(&mut self.format, format, format.label());
+                }
+            });
+        if self.format != previous {
+            let stem = self
+                .path
+                .strip_suffix(previous.extension())
+                .and_then(|stem| stem.strip_suffix('.'))
+                .unwrap_or(&self.path);
+            self.path = format!("{}.{}", stem, self.format.extension());
+        }
+        match self.format {
+            ExportFormat::Svg => {}
+            ExportFormat::Png => {
+                ui.horizontal(|ui| {
+                    ui.label("Size");
+                    ui.add(
+                        egui::DragValue::new(&mut self.options.png_width).clamp_range(1..=16384),
+                    );
+                    ui.label("x");
+                    ui.add(
+                        egui::DragValue::new(&mut self.options.png_height).clamp_range(1..=16384),
+                    );
+                });
+                ui.add(egui::Slider::new(&mut self.options.png_samples, 1..=8).text("Samples"));
+            }
+            ExportFormat::Poster => {
+                let poster = &mut self.options.poster;
+                ui.horizontal(|ui| {
+                    ui.label("Size");
+                    ui.add(egui::DragValue::new(&mut poster.width).clamp_range(1..=100000));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut poster.height).clamp_range(1..=100000));
+                });
+                ui.add(egui::Slider::new(&mut poster.dpi, 72.0..=1200.0).text("DPI"));
+                let size = poster.size_mm();
+                ui.label(format!("Print size {:.0} x {:.0} mm", size.x, size.y));
+                ui.add(egui::Slider::new(&mut poster.margin, 0.0..=100.0).text("Margin (mm)"));
+                ui.add(egui::Slider::new(&mut poster.tile_size, 64..=4096).text("Tile Size"));
+                ui.label(format!(
+                    "Renders {:.0} MB of pixels at a time",
+                    poster.band_bytes() as f32 / 1e6
+                ));
+                ui.add(egui::Slider::new(&mut poster.samples, 1..=8).text("Samples"));
+            }
+            ExportFormat::Pdf | ExportFormat::Eps => edit_page_layout(ui, &mut self.options.page),
+            ExportFormat::GCode | ExportFormat::Hpgl => {
+                edit_page_layout(ui, &mut self.options.page);
+                let plotter = &mut self.options.plotter;
+                ui.add(
+                    egui::Slider::new(&mut plotter.draw_speed, 1.0..=200.0)
+                        .text("Draw Speed (mm/s)"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut plotter.travel_speed, 1.0..=400.0)
+                        .text("Travel Speed (mm/s)"),
+                );
+                ui.add(egui::Slider::new(&mut plotter.pen_delay, 0.0..=1.0).text("Pen Delay (s)"));
+                if self.format == ExportFormat::GCode {
+                    ui.horizontal(|ui| {
+                        ui.label("Pen Up");
+                        ui.text_edit_singleline(&mut plotter.pen_up);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Pen Down");
+                        ui.text_edit_singleline(&mut plotter.pen_down);
+                    });
+                }
+            }
+            ExportFormat::Dxf => edit_dxf_settings(ui, &mut self.options.dxf),
+            ExportFormat::Obj | ExportFormat::Stl => {
+                let solid = &mut self.options.solid;
+                ui.add(egui::Slider::new(&mut solid.scale, 0.01..=10.0).text("Scale"));
+                ui.add(egui::Slider::new(&mut solid.sides, 3..=32).text("Tube Sides"));
+                ui.add(
+                    egui::Slider::new(&mut solid.leaf_thickness, 0.01..=5.0).text("Leaf Thickness"),
+                );
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut self.path);
+        });
+        job_button(ui, "Export", self.progress, &self.status)
+    }
+}
+
+pub struct AnimationPanel {
+    pub settings: AnimationSettings,
+    pub path: String,
+    pub status: Option<String>,
+    /// How far the animation rendering in the background has got, while it runs.
+    pub progress: Option<f32>,
+}
+impl AnimationPanel {
+    pub fn new() -> Self {
+        Self {
+            settings: AnimationSettings::default(),
+            path: format!("growth.{}", AnimationFormat::Gif.extension()),
+            status: None,
+            progress: None,
+        }
+    }
+    /// Returns true when the export button was clicked.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let settings = &mut self.settings;
+        ui.label("Animation");
+        let previous = settings.format;
+        egui::ComboBox::from_label("Animation Format")
+            .selected_text(settings.format.label())
+            .show_ui(ui, |ui| {
+                for format in AnimationFormat::ALL {
+                    ui.selectable_value(&mut settings.format, format, format.label());
+                }
+            });
+        if settings.format != previous {
+            let stem = self
+                .path
+                .strip_suffix(previous.extension())
+                .and_then(|stem| stem.strip_suffix('.'))
+                .unwrap_or(&self.path);
+            self.path = format!("{}.{}", stem, settings.format.extension());
+        }
+        egui::ComboBox::from_label("Growth")
+            .selected_text(settings.growth.label())
+            .show_ui(ui, |ui| {
+                for growth in Growth::ALL {
+                    ui.selectable_value(&mut settings.growth, growth, growth.label());
+                }
+            });
+        match settings.growth {
+            Growth::Levels => {
+                ui.add(egui::Slider::new(&mut settings.first_level, 1..=10).text("First Level"));
+                ui.add(egui::Slider::new(&mut settings.last_level, 1..=10).text("Last Level"));
+                settings.last_level = settings.last_level.max(settings.first_level);
+            }
+            Growth::Timed => {
+                ui.add(egui::Slider::new(&mut settings.last_level, 1..=10).text("Level"));
+                ui.add(egui::Slider::new(&mut settings.frames, 1..=300).text("Frames"));
+            }
+        }
+        ui.add(egui::Slider::new(&mut settings.frame_delay, 10..=5000).text("Frame Delay (ms)"));
+        ui.checkbox(&mut settings.looping, "Loop");
+        ui.horizontal(|ui| {
+            ui.label("Size");
+            ui.add(egui::DragValue::new(&mut settings.width).clamp_range(1..=4096));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut settings.height).clamp_range(1..=4096));
+        });
+        ui.add(egui::Slider::new(&mut settings.samples, 1..=8).text("Samples"));
+        ui.add(egui::Slider::new(&mut settings.margin, 0.0..=200.0).text("Margin"));
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut self.path);
+        });
+        job_button(ui, "Export Animation", self.progress, &self.status)
+    }
+}
+
+/// Renders a frame sequence from a sweep file, starting from the current settings.
+pub struct SweepPanel {
+    pub path: String,
+    pub status: Option<String>,
+    /// How far the frames rendering in the background have got, while they run.
+    pub progress: Option<f32>,
+}
+impl SweepPanel {
+    pub fn new() -> Self {
+        Self {
+            path: "sweep.txt".to_string(),
+            status: None,
+            progress: None,
+        }
+    }
+    /// Returns true when the render button was clicked.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.label("Frame Sequence");
+        ui.horizontal(|ui| {
+            ui.label("Sweep File");
+            ui.text_edit_singleline(&mut self.path);
+        });
+        job_button(ui, "Render Frames", self.progress, &self.status)
+    }
+}
+
+/// A button starting a background job, disabled with a progress bar while the job runs,
+/// and the status the last job left. Returns true when it was clicked.
+fn job_button(
+    ui: &mut egui::Ui,
+    label: &str,
+    progress: Option<f32>,
+    status: &Option<String>,
+) -> bool {
+    let clicked = ui
+        .add_enabled(progress.is_none(), egui::Button::new(label))
+        .clicked();
+    if let Some(progress) = progress {
+        ui.add(egui::ProgressBar::new(progress).show_percentage());
+    }
+    if let Some(status) = status {
+        ui.label(status);
+    }
+    clicked
+}
+
+fn edit_page_layout(ui: &mut egui::Ui, page: &mut PageLayout) {
+    egui::ComboBox::from_label("Page Size")
+        .selected_text(page.size.label())
+        .show_ui(ui, |ui| {
+            for size in PageSize::ALL {
+                ui.selectable_value(&mut page.size, size, size.label());
+            }
+        });
+    if page.size == PageSize::Custom {
+        ui.horizontal(|ui| {
+            ui.label("mm");
+            ui.add(egui::DragValue::new(&mut page.custom.x).clamp_range(1.0..=5000.0));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut page.custom.y).clamp_range(1.0..=5000.0));
+        });
+    }
+    ui.checkbox(&mut page.landscape, "Landscape");
+    ui.add(egui::Slider::new(&mut page.margin, 0.0..=50.0).text("Margin (mm)"));
+    ui.checkbox(&mut page.fit, "Fit To Page");
+}
+
+fn edit_dxf_settings(ui: &mut egui::Ui, dxf: &mut DxfSettings) {
+    egui::ComboBox::from_label("Units")
+        .selected_text(dxf.units.label())
+        .show_ui(ui, |ui| {
+            for units in DxfUnits::ALL {
+                ui.selectable_value(&mut dxf.units, units, units.label());
+            }
+        });
+    ui.horizontal(|ui| {
+        ui.label("Longest Side");
+        ui.add(egui::DragValue::new(&mut dxf.size).clamp_range(0.001..=100000.0));
+        ui.label(dxf.units.label());
+    });
+    egui::ComboBox::from_label("Layers")
+        .selected_text(dxf.layering.label())
+        .show_ui(ui, |ui| {
+            for layering in DxfLayering::ALL {
+                ui.selectable_value(&mut dxf.layering, layering, layering.label());
+            }
+        });
+}
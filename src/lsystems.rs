@@ -1,7 +1,16 @@
+use std::{fmt, ops::ControlFlow};
+
 use lsystem::{LSystem, MapRules};
-use nannou::{geom::Rect, glam::Vec2, Draw};
+use nannou::prelude::*;
+
+use crate::{
+    geometry::Geometry,
+    jitter::{Jitter, JitterRng},
+    pruning::{CollisionGrid, Pruning},
+    stroke::StrokeStyle,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LSystemRules {
     pub axiom: Vec<char>,
     pub rules: Vec<(char, String)>,
@@ -21,29 +30,520 @@ impl LSystemRules {
         let output = system.nth(levels.clone())?.into_iter().collect();
         Some(output)
     }
+    /// Derives the same string as `eval`, tagging every symbol with the generation it was
+    /// produced in. Axiom symbols are generation 0.
+    pub fn eval_generations(&self, levels: &usize) -> Vec<(char, usize)> {
+        let mut symbols: Vec<(char, usize)> = self.axiom.iter().map(|&c| (c, 0)).collect();
+
+        // the lsystem iterator yields the first rewrite of the axiom as its 0th item
+        for generation in 1..=levels + 1 {
+            let mut next = Vec::with_capacity(symbols.len());
+            for (c, born) in symbols {
+                // later rules win, matching MapRules::set_str
+                match self.rules.iter().rev().find(|(k, _)| *k == c) {
+                    Some((_, replacement)) => {
+                        next.extend(replacement.chars().map(|r| (r, generation)));
+                    }
+                    None => next.push((c, born)),
+                }
+            }
+            symbols = next;
+        }
+        symbols
+    }
 }
 
 pub trait DrawableLSystem {
-    fn draw(&self, draw: &Draw, win: &Rect, levels: &usize);
+    /// Interprets the system at `levels` without touching nannou.
+    fn geometry(&self, levels: &usize) -> Geometry;
     fn get_rules(&self) -> LSystemRules;
+    fn stroke_style(&self) -> &StrokeStyle;
 }
 
-pub struct LSystemDrawingParamaters {
-    pub start_pos: Vec2,
+/// Everything the turtle knows at a given point of the interpretation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TurtleState {
     pub pos: Vec2,
     pub angle: f32,
-    pos_stack: Vec<Vec2>,
-    angle_stack: Vec<f32>,
+    /// Number of currently open `[` brackets.
+    pub depth: usize,
+    /// Advanced by `'`.
+    pub color_index: usize,
+    /// Path length travelled from the start, restored when a branch is popped.
+    pub distance: f32,
+    /// Position of the symbol being interpreted in the derived string.
+    pub index: usize,
+    /// Derivation generation of the symbol being interpreted.
+    pub generation: usize,
+    /// Branch opened by the innermost unclosed `[`, 0 for the trunk. Every `[` opens a
+    /// new id.
+    pub branch_id: usize,
+}
+
+/// Where an emitted segment came from, recorded so coloring, statistics and exports
+/// don't have to re-derive it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentMeta {
+    pub depth: usize,
+    pub generation: usize,
+    /// Position of the drawing symbol in the derived string.
+    pub index: usize,
+    /// Path length travelled from the start to the end of the segment.
+    pub arc_length: f32,
+    pub branch_id: usize,
+}
+
+impl SegmentMeta {
+    pub fn from_state(state: &TurtleState) -> Self {
+        SegmentMeta {
+            depth: state.depth,
+            generation: state.generation,
+            index: state.index,
+            arc_length: state.distance,
+            branch_id: state.branch_id,
+        }
+    }
+}
+
+/// What the turtle does when a `]` has no matching `[`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackPolicy {
+    /// Stop interpreting and keep what was drawn so far.
+    Error,
+    /// Skip the `]`.
+    Ignore,
+    /// Jump back to the start position and angle.
+    Reset,
+}
+
+impl StackPolicy {
+    pub const ALL: [StackPolicy; 3] = [StackPolicy::Error, StackPolicy::Ignore, StackPolicy::Reset];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StackPolicy::Error => "Stop",
+            StackPolicy::Ignore => "Ignore",
+            StackPolicy::Reset => "Reset To Start",
+        }
+    }
+}
+
+/// Problems found while interpreting a derived string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TurtleError {
+    UnmatchedPop { index: usize },
+    UnknownSurface { index: usize, name: char },
+}
+
+impl fmt::Display for TurtleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TurtleError::UnmatchedPop { index } => {
+                write!(f, "unmatched ']' at symbol {}", index)
+            }
+            TurtleError::UnknownSurface { index, name } => {
+                write!(f, "no surface named '{}' for '~' at symbol {}", name, index)
+            }
+        }
+    }
+}
+
+/// Bends the heading toward `direction` after every step by `strength * |H x T|`,
+/// as described in The Algorithmic Beauty of Plants. Only the direction of `direction`
+/// matters; a zero vector disables the bend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tropism {
+    pub direction: Vec2,
+    pub strength: f32,
+}
+
+impl Tropism {
+    pub fn new(direction: Vec2, strength: f32) -> Self {
+        Tropism {
+            direction,
+            strength,
+        }
+    }
+    pub fn default() -> Self {
+        Tropism::new(vec2(0.0, -1.0), 0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthScaleMode {
+    Off,
+    /// Shrink once per open bracket.
+    Depth,
+    /// Shrink once per derivation generation of the drawing symbol.
+    Generation,
+}
+
+impl LengthScaleMode {
+    pub const ALL: [LengthScaleMode; 3] = [
+        LengthScaleMode::Off,
+        LengthScaleMode::Depth,
+        LengthScaleMode::Generation,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LengthScaleMode::Off => "Off",
+            LengthScaleMode::Depth => "Per Bracket Depth",
+            LengthScaleMode::Generation => "Per Generation",
+        }
+    }
+}
+
+/// Multiplies step lengths by `factor` raised to the depth or generation, so
+/// self-similar systems keep their proportions as levels increase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthScale {
+    pub mode: LengthScaleMode,
+    pub factor: f32,
+}
+
+impl LengthScale {
+    pub fn new(mode: LengthScaleMode, factor: f32) -> Self {
+        LengthScale { mode, factor }
+    }
+    pub fn default() -> Self {
+        LengthScale::new(LengthScaleMode::Off, 0.7)
+    }
+    pub fn scale(&self, state: &TurtleState) -> f32 {
+        match self.mode {
+            LengthScaleMode::Off => 1.0,
+            LengthScaleMode::Depth => self.factor.powi(state.depth as i32),
+            LengthScaleMode::Generation => self.factor.powi(state.generation as i32),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LSystemDrawingParamaters {
+    pub start_pos: Vec2,
+    pub start_angle: f32,
+    pub state: TurtleState,
+    pub tropism: Tropism,
+    pub jitter: Jitter,
+    pub stack_policy: StackPolicy,
+    pub length_scale: LengthScale,
+    pub pruning: Pruning,
+    rng: JitterRng,
+    stack: Vec<TurtleState>,
+    branches: usize,
+    grid: CollisionGrid,
+    /// Depth of the branch being pruned, if any.
+    pruned_depth: Option<usize>,
 }
 
 impl LSystemDrawingParamaters {
     pub fn new(start_pos: Vec2, angle: f32) -> Self {
         LSystemDrawingParamaters {
             start_pos,
-            pos: start_pos,
-            angle,
-            pos_stack: Vec::new(),
-            angle_stack: Vec::new(),
+            start_angle: angle,
+            state: TurtleState {
+                pos: start_pos,
+                angle,
+                depth: 0,
+                color_index: 0,
+                distance: 0.0,
+                index: 0,
+                generation: 0,
+                branch_id: 0,
+            },
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stack_policy: StackPolicy::Error,
+            length_scale: LengthScale::default(),
+            pruning: Pruning::default(),
+            rng: JitterRng::new(0),
+            stack: Vec::new(),
+            branches: 0,
+            grid: CollisionGrid::new(Pruning::default().cell_size),
+            pruned_depth: None,
+        }
+    }
+    pub fn with_tropism(mut self, tropism: Tropism) -> Self {
+        self.tropism = tropism;
+        self
+    }
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self.rng = JitterRng::new(jitter.seed);
+        self
+    }
+    pub fn with_stack_policy(mut self, stack_policy: StackPolicy) -> Self {
+        self.stack_policy = stack_policy;
+        self
+    }
+    pub fn with_length_scale(mut self, length_scale: LengthScale) -> Self {
+        self.length_scale = length_scale;
+        self
+    }
+    pub fn with_pruning(mut self, pruning: Pruning) -> Self {
+        self.pruning = pruning;
+        self.grid = CollisionGrid::new(pruning.cell_size);
+        self
+    }
+    /// Records which symbol of the derived string is being interpreted.
+    pub fn set_symbol(&mut self, index: usize, generation: usize) {
+        self.state.index = index;
+        self.state.generation = generation;
+    }
+    /// `length` after the length scale for the current depth and generation.
+    pub fn scaled(&self, length: f32) -> f32 {
+        length * self.length_scale.scale(&self.state)
+    }
+    /// Moves `length` along the current heading and returns the start and end of the step.
+    pub fn forward(&mut self, length: f32) -> (Vec2, Vec2) {
+        let jitter = (1.0 + self.jitter.length.sample(&mut self.rng)).max(0.0);
+        let length = self.scaled(length) * jitter;
+        let start = self.state.pos;
+        let end = start + vec2(0.0, length).rotate(self.state.angle);
+        self.state.pos = end;
+        self.state.distance += length.abs();
+        self.bend();
+        self.check_crowding(start, end);
+        (start, end)
+    }
+    /// Whether the current branch was pruned. Nothing should be emitted until the
+    /// pop that closes it.
+    pub fn is_pruned(&self) -> bool {
+        self.pruned_depth.is_some()
+    }
+    fn check_crowding(&mut self, start: Vec2, end: Vec2) {
+        if !self.pruning.is_enabled() || self.is_pruned() {
+            return;
+        }
+        let intersects = self.pruning.intersections && self.grid.intersects(start, end);
+        let dense =
+            self.pruning.max_density > 0 && self.grid.density(end) >= self.pruning.max_density;
+        // only branches are pruned, the trunk always grows
+        let crowded = self.state.depth > 0 && (intersects || dense);
+        if crowded {
+            self.pruned_depth = Some(self.state.depth);
+        } else {
+            self.grid.insert(start, end);
+        }
+    }
+    /// Metadata for the segment the last `forward` emitted.
+    pub fn segment_meta(&self) -> SegmentMeta {
+        SegmentMeta::from_state(&self.state)
+    }
+    fn bend(&mut self) {
+        // only the strength scales the bend, however long the direction vector is
+        let Some(direction) = self.tropism.direction.try_normalize() else {
+            return;
+        };
+        let heading = vec2(0.0, 1.0).rotate(self.state.angle);
+        self.state.angle += self.tropism.strength * heading.perp_dot(direction);
+    }
+    pub fn turn(&mut self, angle: f32) {
+        self.state.angle += angle + self.jitter.angle.sample(&mut self.rng);
+    }
+    pub fn next_color(&mut self) {
+        self.state.color_index += 1;
+    }
+    pub fn push(&mut self) {
+        self.stack.push(self.state);
+        self.state.depth += 1;
+        self.branches += 1;
+        self.state.branch_id = self.branches;
+    }
+    /// Returns to the state saved by the matching `push`. An unmatched pop is handled
+    /// according to the stack policy and always reported; interpretation should stop
+    /// when it is reported under `StackPolicy::Error`.
+    pub fn pop(&mut self) -> Result<(), TurtleError> {
+        let (index, generation) = (self.state.index, self.state.generation);
+        let result = match self.stack.pop() {
+            Some(state) => {
+                self.state = state;
+                Ok(())
+            }
+            None => {
+                if self.stack_policy == StackPolicy::Reset {
+                    self.state.pos = self.start_pos;
+                    self.state.angle = self.start_angle;
+                    self.state.depth = 0;
+                    self.state.distance = 0.0;
+                    self.state.branch_id = 0;
+                    self.pruned_depth = None;
+                }
+                Err(TurtleError::UnmatchedPop { index })
+            }
+        };
+        if self
+            .pruned_depth
+            .map_or(false, |depth| self.state.depth < depth)
+        {
+            self.pruned_depth = None;
+        }
+        self.set_symbol(index, generation);
+        result
+    }
+    /// Interprets a `]`, recording an unmatched pop in `diagnostics`. Continues with
+    /// whether the pop restored a saved state, so turns tied to the bracket are only
+    /// made when it did, or breaks when interpretation should stop.
+    pub fn close_branch(&mut self, diagnostics: &mut Vec<TurtleError>) -> ControlFlow<(), bool> {
+        match self.pop() {
+            Ok(()) => ControlFlow::Continue(true),
+            Err(error) => {
+                diagnostics.push(error);
+                if self.stack_policy == StackPolicy::Error {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(false)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dragon_curve::DragonCurveLSystem, koch_curves::koch_island_rules_object};
+
+    fn dragon() -> DragonCurveLSystem {
+        DragonCurveLSystem::new(vec2(0.0, 0.0), 0.0, 5.0, Hsv::new(0.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn koch_island_first_level() {
+        let rules = koch_island_rules_object();
+        let side = "F-F+F+FF-F-F+F";
+        let expected = [side, side, side, side].join("-");
+        assert_eq!(rules.eval(&0).unwrap(), expected);
+    }
+
+    #[test]
+    fn dragon_levels() {
+        let rules = dragon().get_rules();
+        assert_eq!(rules.eval(&0).unwrap(), "F+G");
+        assert_eq!(rules.eval(&1).unwrap(), "F+G+F-G");
+        assert_eq!(rules.eval(&2).unwrap(), "F+G+F-G+F+G-F-G");
+    }
+
+    #[test]
+    fn generations_match_eval() {
+        for rules in [dragon().get_rules(), koch_island_rules_object()] {
+            for levels in 0..4 {
+                let symbols = rules.eval_generations(&levels);
+                let string: String = symbols.iter().map(|&(c, _)| c).collect();
+                assert_eq!(string, rules.eval(&levels).unwrap());
+                // drawing symbols are rewritten on each pass, so all come from the last one
+                assert!(symbols
+                    .iter()
+                    .filter(|&&(c, _)| c == 'F' || c == 'G')
+                    .all(|&(_, generation)| generation == levels + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn generations_keep_unrewritten_symbols() {
+        let rules = LSystemRules::new(vec!['A', 'X'], vec![('A', "AB".to_string())]);
+        assert_eq!(
+            rules.eval_generations(&1),
+            vec![('A', 2), ('B', 2), ('B', 1), ('X', 0)]
+        );
+    }
+
+    #[test]
+    fn dragon_segment_count_doubles() {
+        for levels in 0..8 {
+            let geometry = dragon().geometry(&levels);
+            assert_eq!(geometry.segment_count(), 2 << levels);
+            // the curve never lifts the pen, so it is one stroke
+            assert_eq!(geometry.polylines.len(), 1);
+        }
+    }
+
+    #[test]
+    fn koch_island_segment_count() {
+        let koch = crate::koch_curves::KochCurveLSystem::with_rules(koch_island_rules_object());
+        for levels in 0..3 {
+            assert_eq!(
+                koch.geometry(&levels).segment_count(),
+                4 * 8usize.pow(levels as u32 + 1)
+            );
+        }
+    }
+
+    fn bent_heading(direction: Vec2, strength: f32, steps: usize) -> f32 {
+        let mut turtle = LSystemDrawingParamaters::new(vec2(0.0, 0.0), 0.0)
+            .with_tropism(Tropism::new(direction, strength));
+        for _ in 0..steps {
+            turtle.forward(1.0);
+        }
+        turtle.state.angle
+    }
+
+    #[test]
+    fn tropism_pulls_the_heading_toward_its_direction() {
+        // heading up, pulled toward +x, which is a clockwise turn
+        let once = bent_heading(vec2(1.0, 0.0), 0.1, 1);
+        assert!((once + 0.1).abs() < 1e-6, "{}", once);
+        let settled = bent_heading(vec2(1.0, 0.0), 0.1, 200);
+        assert!((settled + PI / 2.0).abs() < 1e-3, "{}", settled);
+        let left = bent_heading(vec2(-1.0, 0.0), 0.1, 200);
+        assert!((left - PI / 2.0).abs() < 1e-3, "{}", left);
+    }
+
+    #[test]
+    fn tropism_strength_ignores_direction_length() {
+        assert_eq!(
+            bent_heading(vec2(10.0, 0.0), 0.1, 5),
+            bent_heading(vec2(1.0, 0.0), 0.1, 5)
+        );
+        assert_eq!(bent_heading(vec2(0.0, 0.0), 0.1, 5), 0.0);
+    }
+
+    #[test]
+    fn close_branch_follows_the_stack_policy() {
+        for policy in StackPolicy::ALL {
+            let mut turtle =
+                LSystemDrawingParamaters::new(vec2(1.0, 2.0), 0.5).with_stack_policy(policy);
+            let mut diagnostics = Vec::new();
+            turtle.push();
+            turtle.turn(1.0);
+            assert_eq!(
+                turtle.close_branch(&mut diagnostics),
+                ControlFlow::Continue(true)
+            );
+            assert_eq!(turtle.state.angle, 0.5);
+
+            turtle.forward(3.0);
+            turtle.turn(1.0);
+            let expected = match policy {
+                StackPolicy::Error => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(false),
+            };
+            assert_eq!(turtle.close_branch(&mut diagnostics), expected);
+            assert_eq!(diagnostics, vec![TurtleError::UnmatchedPop { index: 0 }]);
+            let angle = match policy {
+                StackPolicy::Reset => 0.5,
+                _ => 1.5,
+            };
+            assert_eq!(turtle.state.angle, angle, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn unbalanced_brackets_do_not_panic() {
+        use crate::fractal_plant::FractalPlantLSystem;
+
+        let rules = LSystemRules::new(vec!['X'], vec![('X', "F]]F[F]]F".to_string())]);
+        for policy in StackPolicy::ALL {
+            let mut plant = FractalPlantLSystem::with_rules(rules.clone());
+            plant.stack_policy = policy;
+            let geometry = plant.geometry(&1);
+            let (segments, errors) = match policy {
+                StackPolicy::Error => (1, 1),
+                _ => (4, 3),
+            };
+            assert_eq!(geometry.segment_count(), segments, "{:?}", policy);
+            assert_eq!(geometry.diagnostics.len(), errors, "{:?}", policy);
         }
     }
 }
@@ -1,22 +1,63 @@
+mod animation;
+mod cleanup;
+mod color_palette;
 mod dragon_curve;
+mod dxf_export;
+mod export;
 mod fractal_plant;
 mod fractal_tree;
+mod frame_sequence;
+mod geometry;
+mod jitter;
+mod job;
 mod koch_curves;
 mod levy_c_curve;
 mod lsystem_egui;
 mod lsystems;
+mod mesh;
+mod mesh_export;
+mod pdf_export;
+mod plotter_export;
+mod poster;
+mod pruning;
+mod raster;
+mod render;
 mod sierpinski_triangle;
+mod smoothing;
+mod stroke;
+mod surfaces;
+mod svg_export;
+mod symmetry;
 
 use std::borrow::BorrowMut;
 
+use animation::{export_animation, growth_frames};
+use cleanup::{Cleanup, CleanupStats};
+use color_palette::{ColorSource, Palette};
+use export::{export, ExportError, ExportFormat, ExportOptions};
 use fractal_plant::FractalPlantLSystem;
 use fractal_tree::FractalTreeLSystem;
-
-use lsystem_egui::LSystemRulesEditor;
-pub use lsystems::{DrawableLSystem, LSystemDrawingParamaters, LSystemRules};
+use frame_sequence::Sweep;
+use geometry::Geometry;
+use jitter::{Jitter, Noise, NoiseDistribution};
+use job::{poll_job, Job};
+use levy_c_curve::LevyCCurve;
+
+use lsystem_egui::{AnimationPanel, ExportPanel, LSystemRulesEditor, SurfaceEditor, SweepPanel};
+pub use lsystems::{
+    DrawableLSystem, LSystemDrawingParamaters, LSystemRules, LengthScale, LengthScaleMode,
+    SegmentMeta, StackPolicy, Tropism, TurtleError,
+};
+use mesh::Mesh;
 use nannou::{color::FromColor, prelude::*};
 use nannou_egui::{self, egui, Egui};
+use poster::render_poster;
+use pruning::Pruning;
+use render::draw_mesh;
 use sierpinski_triangle::SierpinskiTriangleLSystem;
+use smoothing::{Smoothing, SmoothingMode};
+use stroke::{LineCap, LineJoin, StrokeStyle};
+use symmetry::Symmetry;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum LSystemSelection {
@@ -28,44 +69,35 @@ pub enum LSystemSelection {
     KochCurve,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Settings {
     lsystem_selection: LSystemSelection,
     lsystem_levels: usize,
+    auto_fit: bool,
+    auto_fit_margin: f32,
+    cleanup: Cleanup,
+    smoothing: Smoothing,
+    symmetry: Symmetry,
     fractal_plant_lsystem: FractalPlantLSystem,
     fractal_tree_lsystem: FractalTreeLSystem,
     sierpinski_triangle_lsystem: SierpinskiTriangleLSystem,
     dragon_curve_lsystem: dragon_curve::DragonCurveLSystem,
     koch_curve_lsystem: koch_curves::KochCurveLSystem,
+    levy_c_curve_lsystem: LevyCCurve,
     lsystem_rules_editor: LSystemRulesEditor,
+    surface_editor: SurfaceEditor,
 }
 
-struct Model {
-    settings: Settings,
-    egui: Egui,
-}
-
-fn main() {
-    nannou::app(model).update(update).run();
-}
-
-fn model(app: &App) -> Model {
-    // Create window
-    let window_id = app
-        .new_window()
-        .view(view)
-        .raw_event(raw_window_event)
-        .build()
-        .unwrap();
-    let window = app.window(window_id).unwrap();
-
-    let egui = Egui::from_window(&window);
-
-    Model {
-        egui,
-        settings: Settings {
+impl Settings {
+    fn default() -> Self {
+        Settings {
             lsystem_selection: LSystemSelection::FractalPlant,
             lsystem_levels: 4,
+            auto_fit: false,
+            auto_fit_margin: 20.0,
+            cleanup: Cleanup::default(),
+            smoothing: Smoothing::default(),
+            symmetry: Symmetry::default(),
             sierpinski_triangle_lsystem: SierpinskiTriangleLSystem::new(
                 5.0,
                 vec2(0.0, 0.0),
@@ -95,17 +127,213 @@ fn model(app: &App) -> Model {
             koch_curve_lsystem: koch_curves::KochCurveLSystem::with_rules(
                 koch_curves::koch_pyramid_rules_object(),
             ),
+            levy_c_curve_lsystem: LevyCCurve::default(),
             lsystem_rules_editor: LSystemRulesEditor::new(LSystemRules::new(
                 vec!['F'],
                 vec![('F', "F+F-F-F+F".to_string())],
             )),
+            surface_editor: SurfaceEditor::new(),
+        }
+    }
+    /// The inputs the drawing in `window` is built from, leaving out editor state such as
+    /// the text fields of the rule and surface editors.
+    fn geometry_key(&self, window: Rect) -> GeometryKey {
+        let system = match self.lsystem_selection {
+            LSystemSelection::DragonCurve => {
+                SystemParams::DragonCurve(self.dragon_curve_lsystem.clone())
+            }
+            LSystemSelection::SierpinskiTriangle => {
+                SystemParams::SierpinskiTriangle(self.sierpinski_triangle_lsystem.clone())
+            }
+            LSystemSelection::LevyCCurve => {
+                SystemParams::LevyCCurve(self.levy_c_curve_lsystem.clone())
+            }
+            LSystemSelection::FractalTree => {
+                SystemParams::FractalTree(self.fractal_tree_lsystem.clone())
+            }
+            LSystemSelection::FractalPlant => {
+                SystemParams::FractalPlant(self.fractal_plant_lsystem.clone())
+            }
+            LSystemSelection::KochCurve => SystemParams::KochCurve(self.koch_curve_lsystem.clone()),
+        };
+        GeometryKey {
+            system,
+            levels: self.lsystem_levels,
+            auto_fit: self.auto_fit,
+            auto_fit_margin: self.auto_fit_margin,
+            cleanup: self.cleanup,
+            smoothing: self.smoothing,
+            symmetry: self.symmetry,
+            window,
+        }
+    }
+    fn selected_lsystem(&self) -> &dyn DrawableLSystem {
+        match self.lsystem_selection {
+            LSystemSelection::DragonCurve => &self.dragon_curve_lsystem,
+            LSystemSelection::SierpinskiTriangle => &self.sierpinski_triangle_lsystem,
+            LSystemSelection::LevyCCurve => &self.levy_c_curve_lsystem,
+            LSystemSelection::FractalTree => &self.fractal_tree_lsystem,
+            LSystemSelection::FractalPlant => &self.fractal_plant_lsystem,
+            LSystemSelection::KochCurve => &self.koch_curve_lsystem,
+        }
+    }
+    /// The selected system at `levels` with cleanup, smoothing and symmetry applied.
+    fn geometry(&self, levels: usize) -> Geometry {
+        self.geometry_with_stats(levels).0
+    }
+    /// Like `geometry`, along with what the cleanup removed if it ran.
+    fn geometry_with_stats(&self, levels: usize) -> (Geometry, Option<CleanupStats>) {
+        let mut geometry = self.selected_lsystem().geometry(&levels);
+        let stats = self
+            .cleanup
+            .is_enabled()
+            .then(|| self.cleanup.apply(&mut geometry));
+        self.smoothing.apply(&mut geometry);
+        self.symmetry.apply(&mut geometry);
+        (geometry, stats)
+    }
+    /// Renders the selected system to a PNG without a window, framed around the drawing
+    /// with the auto fit margin.
+    fn export_png(&self, path: &str) -> Result<String, ExportError> {
+        let geometry = self.geometry(self.lsystem_levels);
+        let view = geometry
+            .bounds()
+            .unwrap_or(Rect::from_w_h(0.0, 0.0))
+            .pad(-self.auto_fit_margin);
+        export(
+            ExportFormat::Png,
+            &geometry,
+            self.selected_lsystem().stroke_style(),
+            view,
+            &ExportOptions::default(),
+            path,
+        )
+    }
+}
+
+/// The parameters of the selected system.
+#[derive(Clone, Debug, PartialEq)]
+enum SystemParams {
+    DragonCurve(dragon_curve::DragonCurveLSystem),
+    SierpinskiTriangle(SierpinskiTriangleLSystem),
+    LevyCCurve(LevyCCurve),
+    FractalTree(FractalTreeLSystem),
+    FractalPlant(FractalPlantLSystem),
+    KochCurve(koch_curves::KochCurveLSystem),
+}
+
+/// Everything the cached geometry and mesh depend on.
+#[derive(Clone, Debug, PartialEq)]
+struct GeometryKey {
+    system: SystemParams,
+    levels: usize,
+    auto_fit: bool,
+    auto_fit_margin: f32,
+    cleanup: Cleanup,
+    smoothing: Smoothing,
+    symmetry: Symmetry,
+    window: Rect,
+}
+
+struct Model {
+    settings: Settings,
+    geometry: Geometry,
+    /// What the cleanup removed from `geometry`, if it is enabled.
+    cleanup_stats: Option<CleanupStats>,
+    /// `geometry` tessellated with the selected system's stroke style.
+    mesh: Mesh,
+    /// What the geometry was built from, so it is only rebuilt when that changes.
+    geometry_key: Option<GeometryKey>,
+    export_panel: ExportPanel,
+    /// A poster export rendering in the background.
+    export_job: Option<Job>,
+    animation_panel: AnimationPanel,
+    /// An animation export rendering in the background.
+    animation_job: Option<Job>,
+    sweep_panel: SweepPanel,
+    /// A sweep rendering its frames in the background.
+    sweep_job: Option<Job>,
+    egui: Egui,
+}
+
+fn main() {
+    // `l-systems --png <file>` renders the default drawing and `l-systems --sweep <file>` a
+    // frame sequence, both without opening a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [flag, path] = args.as_slice() {
+        let settings = Settings::default();
+        let result = match flag.as_str() {
+            "--png" => Some(settings.export_png(path).map_err(|e| e.to_string())),
+            "--sweep" => Some(
+                Sweep::load(path)
+                    .and_then(|sweep| sweep.export(&settings, |_| ()))
+                    .map_err(|e| e.to_string()),
+            ),
+            _ => None,
+        };
+        if let Some(result) = result {
+            match result {
+                Ok(summary) => println!("{}", summary),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+    }
+    nannou::app(model).update(update).run();
+}
+
+fn model(app: &App) -> Model {
+    // Create window
+    let window_id = app
+        .new_window()
+        .view(view)
+        .raw_event(raw_window_event)
+        .build()
+        .unwrap();
+    let window = app.window(window_id).unwrap();
+
+    let egui = Egui::from_window(&window);
+
+    Model {
+        egui,
+        geometry: Geometry::new(),
+        cleanup_stats: None,
+        mesh: Mesh::new(),
+        geometry_key: None,
+        export_panel: ExportPanel::new(),
+        export_job: None,
+        animation_panel: AnimationPanel::new(),
+        animation_job: None,
+        sweep_panel: SweepPanel::new(),
+        sweep_job: None,
+        settings: Settings {
+            levy_c_curve_lsystem: levy_c_curve::setup_levy_c_curve_lsystem(app.window_rect()),
+            ..Settings::default()
         },
     }
 }
 
-fn update(_app: &App, model: &mut Model, update: Update) {
+fn update(app: &App, model: &mut Model, update: Update) {
+    let panel = &mut model.export_panel;
+    panel.progress = poll_job(&mut model.export_job, &mut panel.status);
+    let panel = &mut model.animation_panel;
+    panel.progress = poll_job(&mut model.animation_job, &mut panel.status);
+    let panel = &mut model.sweep_panel;
+    panel.progress = poll_job(&mut model.sweep_job, &mut panel.status);
+
     let egui = &mut model.egui;
     let settings = &mut model.settings;
+    let diagnostics = &model.geometry.diagnostics;
+    let cleanup_stats = &model.cleanup_stats;
+    let export_panel = &mut model.export_panel;
+    let animation_panel = &mut model.animation_panel;
+    let sweep_panel = &mut model.sweep_panel;
+    let mut export_requested = false;
+    let mut animation_requested = false;
+    let mut sweep_requested = false;
 
     egui.set_elapsed_time(update.since_start);
     let ctx = egui.begin_frame();
@@ -114,6 +342,15 @@ fn update(_app: &App, model: &mut Model, update: Update) {
         // Resolution slider
         ui.label("Iterations:");
         ui.add(egui::Slider::new(&mut settings.lsystem_levels, 1..=10));
+        ui.checkbox(&mut settings.auto_fit, "Auto Fit");
+        if settings.auto_fit {
+            ui.add(
+                egui::Slider::new(&mut settings.auto_fit_margin, 0.0..=200.0).text("Fit Margin"),
+            );
+        }
+        egui_edit_cleanup(ui, &mut settings.cleanup, cleanup_stats);
+        egui_edit_smoothing(ui, &mut settings.smoothing);
+        egui_edit_symmetry(ui, &ctx, &mut settings.symmetry);
         ui.label("L-System:");
         ui.radio_value(
             &mut settings.lsystem_selection,
@@ -182,6 +419,10 @@ fn update(_app: &App, model: &mut Model, update: Update) {
                 );
 
                 egui_edit_hsv(ui, &mut dragon_curve_settings.draw_color);
+                egui_edit_palette(ui, &mut dragon_curve_settings.palette);
+                egui_edit_tropism(ui, &mut dragon_curve_settings.tropism);
+                egui_edit_jitter(ui, &mut dragon_curve_settings.jitter);
+                egui_edit_stroke(ui, &mut dragon_curve_settings.stroke);
             }
             LSystemSelection::SierpinskiTriangle => {
                 let sierpinski_triangle_settings = &mut settings.sierpinski_triangle_lsystem;
@@ -216,8 +457,52 @@ fn update(_app: &App, model: &mut Model, update: Update) {
                 );
 
                 egui_edit_hsv(ui, &mut sierpinski_triangle_settings.draw_color);
+                egui_edit_palette(ui, &mut sierpinski_triangle_settings.palette);
+                egui_edit_tropism(ui, &mut sierpinski_triangle_settings.tropism);
+                egui_edit_jitter(ui, &mut sierpinski_triangle_settings.jitter);
+                egui_edit_stroke(ui, &mut sierpinski_triangle_settings.stroke);
+            }
+            LSystemSelection::LevyCCurve => {
+                let levy_c_curve_settings = &mut settings.levy_c_curve_lsystem;
+                ui.label("Levy C Curve LSystem Parameters");
+                ui.add(
+                    egui::Slider::new(&mut levy_c_curve_settings.line_length, 0.0..=20.0)
+                        .text("Line Length"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut levy_c_curve_settings.start_angle, -PI..=PI)
+                        .text("Start Angle"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut levy_c_curve_settings.turn_angle, 0.0..=PI)
+                        .text("Turn Angle"),
+                );
+
+                let screen_rect = ctx.screen_rect();
+                let width = screen_rect.width();
+                let height = screen_rect.height();
+
+                ui.add(
+                    egui::Slider::new(
+                        &mut levy_c_curve_settings.start_pos.x,
+                        -width / 2.0..=width / 2.0,
+                    )
+                    .text("Start Pos X"),
+                );
+                ui.add(
+                    egui::Slider::new(
+                        &mut levy_c_curve_settings.start_pos.y,
+                        -height / 2.0..=height / 2.0,
+                    )
+                    .text("Start Pos Y"),
+                );
+
+                egui_edit_hsv(ui, &mut levy_c_curve_settings.draw_color);
+                egui_edit_palette(ui, &mut levy_c_curve_settings.palette);
+                egui_edit_tropism(ui, &mut levy_c_curve_settings.tropism);
+                egui_edit_jitter(ui, &mut levy_c_curve_settings.jitter);
+                egui_edit_stroke(ui, &mut levy_c_curve_settings.stroke);
             }
-            LSystemSelection::LevyCCurve => {}
             LSystemSelection::FractalTree => {
                 let fractal_tree_settings = &mut settings.fractal_tree_lsystem;
                 ui.label("Fractal Tree LSystem Parameters");
@@ -251,6 +536,24 @@ fn update(_app: &App, model: &mut Model, update: Update) {
 
                 egui_edit_hsv(ui, &mut fractal_tree_settings.branch_color);
                 egui_edit_hsv(ui, &mut fractal_tree_settings.leaf_color);
+                ui.add(
+                    egui::Slider::new(&mut fractal_tree_settings.leaf_stem_length, 0.0..=20.0)
+                        .text("Leaf Stem Length"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut fractal_tree_settings.leaf_size, 0.0..=20.0)
+                        .text("Leaf Size"),
+                );
+                egui_edit_length_scale(ui, &mut fractal_tree_settings.length_scale);
+                egui_edit_palette(ui, &mut fractal_tree_settings.palette);
+                egui_edit_tropism(ui, &mut fractal_tree_settings.tropism);
+                egui_edit_jitter(ui, &mut fractal_tree_settings.jitter);
+                egui_edit_stroke(ui, &mut fractal_tree_settings.stroke);
+                egui_edit_stack_policy(ui, &mut fractal_tree_settings.stack_policy);
+                egui_edit_pruning(ui, &mut fractal_tree_settings.pruning);
+                settings
+                    .surface_editor
+                    .show(ui, &mut fractal_tree_settings.surfaces);
             }
             LSystemSelection::FractalPlant => {
                 let fractal_plant_settings = &mut settings.fractal_plant_lsystem;
@@ -285,6 +588,15 @@ fn update(_app: &App, model: &mut Model, update: Update) {
                 );
 
                 egui_edit_hsv(ui, &mut fractal_plant_settings.draw_color);
+                egui_edit_palette(ui, &mut fractal_plant_settings.palette);
+                egui_edit_tropism(ui, &mut fractal_plant_settings.tropism);
+                egui_edit_jitter(ui, &mut fractal_plant_settings.jitter);
+                egui_edit_stroke(ui, &mut fractal_plant_settings.stroke);
+                egui_edit_stack_policy(ui, &mut fractal_plant_settings.stack_policy);
+                egui_edit_pruning(ui, &mut fractal_plant_settings.pruning);
+                settings
+                    .surface_editor
+                    .show(ui, &mut fractal_plant_settings.surfaces);
             }
             LSystemSelection::KochCurve => {
                 let koch_curve_settings = &mut settings.koch_curve_lsystem;
@@ -298,6 +610,10 @@ fn update(_app: &App, model: &mut Model, update: Update) {
                     egui::Slider::new(&mut koch_curve_settings.start_angle, -PI..=PI)
                         .text("Start Angle"),
                 );
+                ui.add(
+                    egui::Slider::new(&mut koch_curve_settings.turn_angle, 0.0..=PI)
+                        .text("Turn Angle"),
+                );
 
                 let screen_rect = ctx.screen_rect();
                 let width = screen_rect.width();
@@ -319,9 +635,108 @@ fn update(_app: &App, model: &mut Model, update: Update) {
                 );
 
                 egui_edit_hsv(ui, &mut koch_curve_settings.draw_color);
+                egui_edit_palette(ui, &mut koch_curve_settings.palette);
+                egui_edit_tropism(ui, &mut koch_curve_settings.tropism);
+                egui_edit_jitter(ui, &mut koch_curve_settings.jitter);
+                egui_edit_stroke(ui, &mut koch_curve_settings.stroke);
+            }
+        }
+
+        ui.separator();
+        export_requested = export_panel.show(ui);
+        ui.separator();
+        animation_requested = animation_panel.show(ui);
+        ui.separator();
+        sweep_requested = sweep_panel.show(ui);
+
+        if !diagnostics.is_empty() {
+            ui.separator();
+            ui.label("Diagnostics");
+            for diagnostic in diagnostics.iter().take(10) {
+                ui.colored_label(egui::Color32::YELLOW, diagnostic.to_string());
+            }
+            if diagnostics.len() > 10 {
+                ui.label(format!("... and {} more", diagnostics.len() - 10));
             }
         }
     });
+
+    let window = app.window_rect();
+    let key = model.settings.geometry_key(window);
+    if model.geometry_key.as_ref() != Some(&key) {
+        (model.geometry, model.cleanup_stats) = model
+            .settings
+            .geometry_with_stats(model.settings.lsystem_levels);
+        if model.settings.auto_fit {
+            model
+                .geometry
+                .fit_to(window.pad(model.settings.auto_fit_margin));
+        }
+        model.mesh = Mesh::from_geometry(
+            &model.geometry,
+            model.settings.selected_lsystem().stroke_style(),
+        );
+        model.geometry_key = Some(key);
+    }
+
+    if export_requested {
+        let panel = &mut model.export_panel;
+        let stroke = model.settings.selected_lsystem().stroke_style();
+        if panel.format == ExportFormat::Poster {
+            // posters can take minutes, so they render without blocking the window
+            let (geometry, stroke) = (model.geometry.clone(), stroke.clone());
+            let (poster, path) = (panel.options.poster, panel.path.clone());
+            model.export_job = Some(Job::spawn(poster.height as usize, move |progress| {
+                render_poster(&geometry, &stroke, &poster, path, |rows| progress.set(rows))
+            }));
+            panel.status = None;
+        } else {
+            panel.status = Some(
+                match export(
+                    panel.format,
+                    &model.geometry,
+                    stroke,
+                    window,
+                    &panel.options,
+                    &panel.path,
+                ) {
+                    Ok(summary) => summary,
+                    Err(error) => error.to_string(),
+                },
+            );
+        }
+    }
+
+    if animation_requested {
+        let panel = &mut model.animation_panel;
+        let settings = model.settings.clone();
+        let (animation, path) = (panel.settings.clone(), panel.path.clone());
+        model.animation_job = Some(Job::spawn(animation.frame_count(), move |progress| {
+            let frames = growth_frames(&animation, |levels| settings.geometry(levels));
+            export_animation(
+                &animation,
+                frames,
+                settings.selected_lsystem().stroke_style(),
+                path,
+                |frames| progress.set(frames),
+            )
+        }));
+        panel.status = None;
+    }
+
+    if sweep_requested {
+        let panel = &mut model.sweep_panel;
+        match Sweep::load(&panel.path) {
+            Ok(sweep) => {
+                let settings = model.settings.clone();
+                model.sweep_job = Some(Job::spawn(sweep.frames, move |progress| {
+                    sweep.export(&settings, |frames| progress.set(frames))
+                }));
+                panel.status = None;
+            }
+            Err(error) => panel.status = Some(error.to_string()),
+        }
+    }
 }
 
 fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
@@ -331,46 +746,14 @@ fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event:
 
 fn view(app: &App, model: &Model, frame: Frame) {
     // Begin drawing
-    let win = app.window_rect();
     let _t = app.time;
     let draw = app.draw();
-    let settings = &model.settings;
 
     // Clear the background to black.
     draw.background().color(BLACK);
 
-    match model.settings.lsystem_selection {
-        LSystemSelection::DragonCurve => {
-            settings
-                .dragon_curve_lsystem
-                .draw(&draw, &win, &settings.lsystem_levels);
-        }
-        LSystemSelection::SierpinskiTriangle => {
-            settings
-                .sierpinski_triangle_lsystem
-                .draw(&draw, &win, &settings.lsystem_levels);
-        }
-        LSystemSelection::LevyCCurve => {
-            let levy_c_drawable = levy_c_curve::setup_levy_c_curve_lsystem(app.window_rect());
+    draw_mesh(&draw, &model.mesh);
 
-            levy_c_drawable.draw(&draw, &win, &settings.lsystem_levels)
-        }
-        LSystemSelection::FractalTree => {
-            settings
-                .fractal_tree_lsystem
-                .draw(&draw, &win, &settings.lsystem_levels);
-        }
-        LSystemSelection::FractalPlant => {
-            settings
-                .fractal_plant_lsystem
-                .draw(&draw, &win, &settings.lsystem_levels);
-        }
-        LSystemSelection::KochCurve => {
-            settings
-                .koch_curve_lsystem
-                .draw(&draw, &win, &settings.lsystem_levels);
-        }
-    }
     // Write the result of our drawing to the window's frame.
     draw.to_frame(app, &frame).unwrap();
     model.egui.draw_to_frame(&frame).unwrap();
@@ -390,3 +773,230 @@ fn egui_edit_hsv(ui: &mut egui::Ui, color: &mut Hsv) {
         *color = nannou::color::Hsv::new(egui_hsv.h, egui_hsv.s, egui_hsv.v);
     }
 }
+
+fn egui_edit_palette(ui: &mut egui::Ui, palette: &mut Palette) {
+    egui::ComboBox::from_label("Color Source")
+        .selected_text(palette.source.label())
+        .show_ui(ui, |ui| {
+            for source in ColorSource::ALL {
+                ui.selectable_value(&mut palette.source, source, source.label());
+            }
+        });
+    if palette.source == ColorSource::Fixed {
+        return;
+    }
+
+    ui.add(
+        egui::Slider::new(&mut palette.scale, 0.001..=10.0)
+            .logarithmic(true)
+            .text("Color Scale"),
+    );
+    ui.checkbox(&mut palette.repeat, "Repeat Gradient");
+
+    ui.label("Gradient");
+    let mut removed = None;
+    for (i, (position, color)) in palette.gradient.stops.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(position, 0.0..=1.0));
+            egui_edit_hsv(ui, color);
+            if ui.button("X").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        palette.gradient.stops.remove(i);
+    }
+
+    if ui.button("Add Stop").clicked() {
+        palette.gradient.stops.push((1.0, hsv(0.0, 0.0, 1.0)));
+    }
+}
+
+fn egui_edit_tropism(ui: &mut egui::Ui, tropism: &mut Tropism) {
+    ui.label("Tropism");
+    ui.add(egui::Slider::new(&mut tropism.direction.x, -1.0..=1.0).text("Tropism X"));
+    ui.add(egui::Slider::new(&mut tropism.direction.y, -1.0..=1.0).text("Tropism Y"));
+    ui.add(egui::Slider::new(&mut tropism.strength, -1.0..=1.0).text("Susceptibility"));
+}
+
+fn egui_edit_jitter(ui: &mut egui::Ui, jitter: &mut Jitter) {
+    ui.label("Jitter");
+    ui.horizontal(|ui| {
+        ui.label("Seed");
+        ui.add(egui::DragValue::new(&mut jitter.seed));
+    });
+    egui_edit_noise(ui, "Angle Noise", &mut jitter.angle, 0.0..=PI / 4.0);
+    egui_edit_noise(ui, "Length Noise", &mut jitter.length, 0.0..=0.5);
+}
+
+fn egui_edit_noise(
+    ui: &mut egui::Ui,
+    label: &str,
+    noise: &mut Noise,
+    range: std::ops::RangeInclusive<f32>,
+) {
+    egui::ComboBox::from_label(label)
+        .selected_text(noise.distribution.label())
+        .show_ui(ui, |ui| {
+            for distribution in NoiseDistribution::ALL {
+                ui.selectable_value(&mut noise.distribution, distribution, distribution.label());
+            }
+        });
+    ui.add(egui::Slider::new(&mut noise.amount, range).text(label));
+}
+
+fn egui_edit_stack_policy(ui: &mut egui::Ui, stack_policy: &mut StackPolicy) {
+    egui::ComboBox::from_label("Unmatched ]")
+        .selected_text(stack_policy.label())
+        .show_ui(ui, |ui| {
+            for policy in StackPolicy::ALL {
+                ui.selectable_value(stack_policy, policy, policy.label());
+            }
+        });
+}
+
+fn egui_edit_cleanup(ui: &mut egui::Ui, cleanup: &mut Cleanup, stats: &Option<CleanupStats>) {
+    ui.checkbox(&mut cleanup.remove_overlaps, "Remove Overlaps");
+    ui.checkbox(&mut cleanup.merge_collinear, "Merge Collinear");
+    if cleanup.is_enabled() {
+        ui.add(
+            egui::Slider::new(&mut cleanup.epsilon, 0.001..=1.0)
+                .logarithmic(true)
+                .text("Epsilon"),
+        );
+        if let Some(stats) = stats {
+            ui.label(stats.summary());
+        }
+    }
+}
+
+fn egui_edit_smoothing(ui: &mut egui::Ui, smoothing: &mut Smoothing) {
+    egui::ComboBox::from_label("Smoothing")
+        .selected_text(smoothing.mode.label())
+        .show_ui(ui, |ui| {
+            for mode in SmoothingMode::ALL {
+                ui.selectable_value(&mut smoothing.mode, mode, mode.label());
+            }
+        });
+    if smoothing.mode != SmoothingMode::Off {
+        ui.add(egui::Slider::new(&mut smoothing.tension, 0.0..=1.0).text("Tension"));
+        ui.add(egui::Slider::new(&mut smoothing.subdivisions, 1..=16).text("Subdivisions"));
+    }
+}
+
+fn egui_edit_length_scale(ui: &mut egui::Ui, length_scale: &mut LengthScale) {
+    egui::ComboBox::from_label("Length Scale")
+        .selected_text(length_scale.mode.label())
+        .show_ui(ui, |ui| {
+            for mode in LengthScaleMode::ALL {
+                ui.selectable_value(&mut length_scale.mode, mode, mode.label());
+            }
+        });
+    if length_scale.mode != LengthScaleMode::Off {
+        ui.add(egui::Slider::new(&mut length_scale.factor, 0.1..=1.5).text("Scale Factor"));
+    }
+}
+
+fn egui_edit_symmetry(ui: &mut egui::Ui, ctx: &egui::Context, symmetry: &mut Symmetry) {
+    ui.label("Symmetry");
+    ui.add(egui::Slider::new(&mut symmetry.copies, 1..=24).text("Copies"));
+    ui.checkbox(&mut symmetry.mirror, "Mirror");
+    if symmetry.mirror {
+        ui.add(egui::Slider::new(&mut symmetry.mirror_angle, -PI..=PI).text("Mirror Angle"));
+    }
+    if symmetry.is_identity() {
+        return;
+    }
+
+    let screen_rect = ctx.screen_rect();
+    let width = screen_rect.width();
+    let height = screen_rect.height();
+
+    ui.add(egui::Slider::new(&mut symmetry.center.x, -width / 2.0..=width / 2.0).text("Center X"));
+    ui.add(
+        egui::Slider::new(&mut symmetry.center.y, -height / 2.0..=height / 2.0).text("Center Y"),
+    );
+    ui.add(egui::Slider::new(&mut symmetry.hue_offset, 0.0..=1.0).text("Hue Offset"));
+}
+
+fn egui_edit_stroke(ui: &mut egui::Ui, stroke: &mut StrokeStyle) {
+    ui.label("Stroke");
+    egui::ComboBox::from_label("Line Cap")
+        .selected_text(stroke.cap.label())
+        .show_ui(ui, |ui| {
+            for cap in LineCap::ALL {
+                ui.selectable_value(&mut stroke.cap, cap, cap.label());
+            }
+        });
+    egui::ComboBox::from_label("Line Join")
+        .selected_text(stroke.join.label())
+        .show_ui(ui, |ui| {
+            for join in LineJoin::ALL {
+                ui.selectable_value(&mut stroke.join, join, join.label());
+            }
+        });
+
+    ui.label("Dash Pattern");
+    let mut removed = None;
+    for (i, length) in stroke.dashes.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            let label = if i % 2 == 0 { "Dash" } else { "Gap" };
+            ui.add(egui::Slider::new(length, 0.0..=50.0).text(label));
+            if ui.button("X").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        stroke.dashes.remove(i);
+    }
+    if ui.button("Add Dash").clicked() {
+        stroke.dashes.push(5.0);
+    }
+    if !stroke.dashes.is_empty() {
+        ui.add(egui::Slider::new(&mut stroke.dash_offset, 0.0..=50.0).text("Dash Offset"));
+    }
+
+    ui.add(egui::Slider::new(&mut stroke.glow.passes, 0..=8).text("Glow Passes"));
+    if stroke.glow.passes > 0 {
+        ui.add(egui::Slider::new(&mut stroke.glow.width, 0.0..=40.0).text("Glow Width"));
+        ui.add(egui::Slider::new(&mut stroke.glow.intensity, 0.0..=1.0).text("Glow Intensity"));
+    }
+}
+
+fn egui_edit_pruning(ui: &mut egui::Ui, pruning: &mut Pruning) {
+    ui.label("Pruning");
+    ui.checkbox(&mut pruning.intersections, "Prune Intersections");
+    ui.add(egui::Slider::new(&mut pruning.max_density, 0..=20).text("Max Segments Per Cell"));
+    if pruning.is_enabled() {
+        ui.add(egui::Slider::new(&mut pruning.cell_size, 1.0..=50.0).text("Cell Size"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_key_ignores_what_is_not_drawn() {
+        let window = Rect::from_w_h(800.0, 600.0);
+        let settings = Settings::default();
+        let key = settings.geometry_key(window);
+
+        let mut other = settings.clone();
+        other.dragon_curve_lsystem.line_length = 12.0;
+        other.lsystem_rules_editor = LSystemRulesEditor::new(LSystemRules::new(vec!['G'], vec![]));
+        assert_eq!(other.geometry_key(window), key);
+
+        other.lsystem_selection = LSystemSelection::DragonCurve;
+        assert_ne!(other.geometry_key(window), key);
+        let mut other = settings.clone();
+        other.lsystem_levels += 1;
+        assert_ne!(other.geometry_key(window), key);
+        let mut other = settings.clone();
+        other.fractal_plant_lsystem.stroke.dash_offset = 3.0;
+        assert_ne!(other.geometry_key(window), key);
+        assert_ne!(settings.geometry_key(Rect::from_w_h(800.0, 601.0)), key);
+    }
+}
@@ -0,0 +1,447 @@
+use nannou::prelude::*;
+
+use crate::{
+    geometry::Geometry,
+    stroke::{LineCap, LineJoin, StrokeStyle},
+};
+
+/// Joins sharper than this fall back to a bevel, like SVG's default miter limit.
+const MITER_LIMIT: f32 = 4.0;
+/// Triangles used for a full circle of a round cap, join or point.
+const ROUND_STEPS: usize = 16;
+
+/// Triangles for a whole `Geometry`, built once on the CPU and submitted as a single
+/// indexed mesh every frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vec2>,
+    pub colors: Vec<Hsva>,
+    /// Three indices into `vertices` per triangle.
+    pub indices: Vec<usize>,
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Mesh::default()
+    }
+    /// Tessellates strokes with their glow underneath, dashes, caps and joins, then
+    /// polygons and points on top.
+    pub fn from_geometry(geometry: &Geometry, stroke: &StrokeStyle) -> Self {
+        let mut mesh = Mesh::new();
+        let layers = stroke.glow.layers().into_iter().chain([(0.0, 1.0)]);
+        for (extra_width, alpha) in layers {
+            for polyline in &geometry.polylines {
+                for run in stroke.dash(&polyline.points, &polyline.colors) {
+                    let run: Vec<(Vec2, Hsva)> = run
+                        .into_iter()
+                        .map(|(p, c)| (p, with_alpha(c, alpha)))
+                        .collect();
+                    mesh.stroke(&run, polyline.width + extra_width, stroke);
+                }
+            }
+        }
+        for polygon in &geometry.polygons {
+            mesh.fill(&polygon.points, with_alpha(polygon.color, 1.0));
+        }
+        for point in &geometry.points {
+            mesh.disc(point.pos, point.radius, with_alpha(point.color, 1.0));
+        }
+        mesh
+    }
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+    /// The triangles whose bounding boxes touch `area`, so tiles of a large image only
+    /// rasterize what can cover them.
+    pub fn within(&self, area: Rect) -> Mesh {
+        let mut mesh = Mesh::new();
+        let mut remap = vec![usize::MAX; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let corners = [0, 1, 2].map(|i| self.vertices[triangle[i]]);
+            let min = corners[0].min(corners[1]).min(corners[2]);
+            let max = corners[0].max(corners[1]).max(corners[2]);
+            if max.x < area.left()
+                || min.x > area.right()
+                || max.y < area.bottom()
+                || min.y > area.top()
+            {
+                continue;
+            }
+            for &index in triangle {
+                if remap[index] == usize::MAX {
+                    remap[index] = mesh.vertex(self.vertices[index], self.colors[index]);
+                }
+                mesh.indices.push(remap[index]);
+            }
+        }
+        mesh
+    }
+    fn vertex(&mut self, pos: Vec2, color: Hsva) -> usize {
+        self.vertices.push(pos);
+        self.colors.push(color);
+        self.vertices.len() - 1
+    }
+    fn triangle(&mut self, a: usize, b: usize, c: usize) {
+        self.indices.extend([a, b, c]);
+    }
+    /// A thick line through `run`, one quad per segment plus the joins between them.
+    pub fn stroke(&mut self, run: &[(Vec2, Hsva)], width: f32, style: &StrokeStyle) {
+        let half = width / 2.0;
+        // zero length segments have no direction to offset along
+        let mut run = run.to_vec();
+        run.dedup_by(|a, b| a.0 == b.0);
+        if run.len() < 2 || half <= 0.0 {
+            return;
+        }
+        let closed = run.len() > 3 && run.first().map(|p| p.0) == run.last().map(|p| p.0);
+
+        for pair in run.windows(2) {
+            let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+            let normal = normal(p1 - p0) * half;
+            let a = self.vertex(p0 + normal, c0);
+            let b = self.vertex(p0 - normal, c0);
+            let c = self.vertex(p1 + normal, c1);
+            let d = self.vertex(p1 - normal, c1);
+            self.triangle(a, b, c);
+            self.triangle(b, d, c);
+        }
+
+        let n = run.len();
+        for i in 1..n - 1 {
+            self.join(run[i - 1].0, run[i], run[i + 1].0, half, style.join);
+        }
+        if closed {
+            self.join(run[n - 2].0, run[0], run[1].0, half, style.join);
+        } else {
+            self.cap(run[0], run[0].0 - run[1].0, half, style.cap);
+            self.cap(run[n - 1], run[n - 1].0 - run[n - 2].0, half, style.cap);
+        }
+    }
+    /// Fills the wedge on the outside of the corner at `vertex`.
+    fn join(&mut self, prev: Vec2, vertex: (Vec2, Hsva), next: Vec2, half: f32, join: LineJoin) {
+        let (p, color) = vertex;
+        let (d0, d1) = ((p - prev).normalize(), (next - p).normalize());
+        let turn = d0.perp_dot(d1);
+        if turn.abs() < 1e-6 {
+            return;
+        }
+        // turning left opens a gap on the right
+        let side = -turn.signum();
+        let (n0, n1) = (normal(d0) * half * side, normal(d1) * half * side);
+        let center = self.vertex(p, color);
+        let a = self.vertex(p + n0, color);
+        let b = self.vertex(p + n1, color);
+
+        match join {
+            LineJoin::Miter => {
+                let bisector = (n0 + n1).normalize();
+                let stretch = 1.0 / bisector.dot(n0 / half);
+                if stretch.is_finite() && stretch <= MITER_LIMIT {
+                    let m = self.vertex(p + bisector * half * stretch, color);
+                    self.triangle(center, a, m);
+                    self.triangle(center, m, b);
+                } else {
+                    self.triangle(center, a, b);
+                }
+            }
+            LineJoin::Round => {
+                let sweep = n0.perp_dot(n1).atan2(n0.dot(n1));
+                self.arc(center, p, n0, sweep, color);
+            }
+            LineJoin::Bevel => self.triangle(center, a, b),
+        }
+    }
+    /// Closes the end at `end`, with `outward` pointing away from the line.
+    fn cap(&mut self, end: (Vec2, Hsva), outward: Vec2, half: f32, cap: LineCap) {
+        let (p, color) = end;
+        let outward = outward.normalize() * half;
+        let side = normal(outward);
+        match cap {
+            LineCap::Butt => {}
+            LineCap::Square => {
+                let a = self.vertex(p + side, color);
+                let b = self.vertex(p - side, color);
+                let c = self.vertex(p + side + outward, color);
+                let d = self.vertex(p - side + outward, color);
+                self.triangle(a, b, c);
+                self.triangle(b, d, c);
+            }
+            LineCap::Round => {
+                let center = self.vertex(p, color);
+                // from one side of the line round through `outward` to the other
+                self.arc(center, p, side, -PI, color);
+            }
+        }
+    }
+    /// A fan around `p` from `p + start`, turning counterclockwise by `sweep` radians.
+    fn arc(&mut self, center: usize, p: Vec2, start: Vec2, sweep: f32, color: Hsva) {
+        let steps = ((sweep.abs() / TAU * ROUND_STEPS as f32).ceil() as usize).max(1);
+        let step = sweep / steps as f32;
+        let mut previous = self.vertex(p + start, color);
+        for i in 1..=steps {
+            let next = self.vertex(p + start.rotate(step * i as f32), color);
+            self.triangle(center, previous, next);
+            previous = next;
+        }
+    }
+    /// Fills a simple polygon, concave or not.
+    pub fn fill(&mut self, points: &[Vec2], color: Hsva) {
+        let mut points = points.to_vec();
+        if points.len() > 3 && points.first() == points.last() {
+            points.pop();
+        }
+        let base = self.vertices.len();
+        for &p in &points {
+            self.vertex(p, color);
+        }
+        for [a, b, c] in triangulate(&points) {
+            self.triangle(base + a, base + b, base + c);
+        }
+    }
+    pub fn disc(&mut self, pos: Vec2, radius: f32, color: Hsva) {
+        let center = self.vertex(pos, color);
+        self.arc(center, pos, vec2(radius, 0.0), TAU, color);
+    }
+}
+
+fn with_alpha(color: Hsv, alpha: f32) -> Hsva {
+    Hsva::new(color.hue, color.saturation, color.value, alpha)
+}
+
+fn normal(direction: Vec2) -> Vec2 {
+    let d = direction.normalize();
+    vec2(-d.y, d.x)
+}
+
+/// Ear clipping triangulation of a simple polygon, returning indices into `points`.
+/// Falls back to a fan for whatever is left if the polygon self-intersects.
+pub fn triangulate(points: &[Vec2]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+    if points.len() < 3 {
+        return triangles;
+    }
+    let area: f32 = (0..points.len())
+        .map(|i| points[i].perp_dot(points[(i + 1) % points.len()]))
+        .sum();
+    let orientation = area.signum();
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (
+                points[remaining[(i + n - 1) % n]],
+                points[remaining[i]],
+                points[remaining[(i + 1) % n]],
+            );
+            if (b - a).perp_dot(c - b) * orientation <= 0.0 {
+                return false;
+            }
+            remaining
+                .iter()
+                .map(|&j| points[j])
+                .filter(|&p| p != a && p != b && p != c)
+                .all(|p| !in_triangle(p, a, b, c))
+        });
+        let Some(i) = ear else {
+            break;
+        };
+        triangles.push([
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        ]);
+        remaining.remove(i);
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let (d0, d1, d2) = (
+        (b - a).perp_dot(p - a),
+        (c - b).perp_dot(p - b),
+        (a - c).perp_dot(p - c),
+    );
+    (d0 >= 0.0 && d1 >= 0.0 && d2 >= 0.0) || (d0 <= 0.0 && d1 <= 0.0 && d2 <= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(points: &[Vec2]) -> Vec<(Vec2, Hsva)> {
+        let color = Hsva::new(0.0, 1.0, 1.0, 1.0);
+        points.iter().map(|&p| (p, color)).collect()
+    }
+
+    fn style(cap: LineCap, join: LineJoin) -> StrokeStyle {
+        StrokeStyle::new(cap, join)
+    }
+
+    fn stroked(points: &[Vec2], width: f32, style: &StrokeStyle) -> Mesh {
+        let mut mesh = Mesh::new();
+        mesh.stroke(&run(points), width, style);
+        mesh
+    }
+
+    /// Signed area of triangle `t`, positive when counterclockwise.
+    fn area(mesh: &Mesh, t: &[usize]) -> f32 {
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[t[i]]);
+        (b - a).perp_dot(c - a) / 2.0
+    }
+
+    #[test]
+    fn straight_stroke_is_one_quad() {
+        let mesh = stroked(
+            &[vec2(0.0, 0.0), vec2(10.0, 0.0)],
+            2.0,
+            &style(LineCap::Butt, LineJoin::Miter),
+        );
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(mesh.colors.len(), mesh.vertices.len());
+        let total: f32 = mesh.indices.chunks(3).map(|t| area(&mesh, t).abs()).sum();
+        assert!((total - 20.0).abs() < 1e-4);
+        for p in &mesh.vertices {
+            assert_eq!(p.y.abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn square_caps_extend_the_stroke() {
+        let mesh = stroked(
+            &[vec2(0.0, 0.0), vec2(10.0, 0.0)],
+            2.0,
+            &style(LineCap::Square, LineJoin::Miter),
+        );
+        assert_eq!(mesh.vertices.len(), 12);
+        assert_eq!(mesh.indices.len(), 18);
+        let xs = mesh.vertices.iter().map(|p| p.x);
+        assert_eq!(xs.clone().fold(f32::MAX, f32::min), -1.0);
+        assert_eq!(xs.fold(f32::MIN, f32::max), 11.0);
+    }
+
+    #[test]
+    fn round_caps_are_half_discs() {
+        let mesh = stroked(
+            &[vec2(0.0, 0.0), vec2(10.0, 0.0)],
+            2.0,
+            &style(LineCap::Round, LineJoin::Miter),
+        );
+        // each cap is a center, a start and half of ROUND_STEPS around
+        let cap_vertices = 2 + ROUND_STEPS / 2;
+        assert_eq!(mesh.vertices.len(), 4 + 2 * cap_vertices);
+        assert_eq!(mesh.indices.len(), 6 + 2 * 3 * ROUND_STEPS / 2);
+        for p in &mesh.vertices[4..] {
+            let end = if p.x < 5.0 {
+                vec2(0.0, 0.0)
+            } else {
+                vec2(10.0, 0.0)
+            };
+            assert!(p.distance(end) <= 1.0 + 1e-4);
+        }
+        // the caps bulge out past both ends by the half width
+        let xs = mesh.vertices.iter().map(|p| p.x);
+        assert!((xs.clone().fold(f32::MAX, f32::min) + 1.0).abs() < 1e-4);
+        assert!((xs.fold(f32::MIN, f32::max) - 11.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn right_angle_gets_a_miter() {
+        let mesh = stroked(
+            &[vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(10.0, 10.0)],
+            2.0,
+            &style(LineCap::Butt, LineJoin::Miter),
+        );
+        // two quads, then center, both offsets and the miter tip
+        assert_eq!(mesh.vertices.len(), 12);
+        assert_eq!(mesh.indices.len(), 18);
+        assert!(mesh.vertices[11].distance(vec2(11.0, -1.0)) < 1e-4);
+    }
+
+    #[test]
+    fn sharp_miter_falls_back_to_bevel() {
+        // turning back by almost 180 degrees stretches the miter far past the limit
+        let points = [vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(0.0, 1.0)];
+        let miter = stroked(&points, 2.0, &style(LineCap::Butt, LineJoin::Miter));
+        let bevel = stroked(&points, 2.0, &style(LineCap::Butt, LineJoin::Bevel));
+        assert_eq!(miter, bevel);
+        assert_eq!(miter.vertices.len(), 11);
+        assert_eq!(miter.indices.len(), 15);
+        assert!(miter.vertices.iter().all(|p| p.x <= 11.0));
+    }
+
+    #[test]
+    fn straight_joins_add_nothing() {
+        let mesh = stroked(
+            &[vec2(0.0, 0.0), vec2(5.0, 0.0), vec2(10.0, 0.0)],
+            2.0,
+            &style(LineCap::Butt, LineJoin::Round),
+        );
+        assert_eq!(mesh.indices.len(), 12);
+    }
+
+    #[test]
+    fn ear_clipping_concave_polygon() {
+        // an arrow pointing right with a notch in its back, counterclockwise
+        let points = [
+            vec2(0.0, 0.0),
+            vec2(4.0, 2.0),
+            vec2(0.0, 4.0),
+            vec2(1.0, 2.0),
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), points.len() - 2);
+        // the reflex vertex can't be an ear, so no triangle spans the notch
+        assert!(!triangles.contains(&[2, 3, 0]) && !triangles.contains(&[0, 2, 3]));
+
+        let mut mesh = Mesh::new();
+        mesh.fill(&points, Hsva::new(0.0, 1.0, 1.0, 1.0));
+        // every triangle keeps the polygon's winding, and together they cover its area
+        let areas: Vec<f32> = mesh.indices.chunks(3).map(|t| area(&mesh, t)).collect();
+        assert!(areas.iter().all(|&a| a > 0.0));
+        assert!((areas.iter().sum::<f32>() - 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ear_clipping_clockwise_comb() {
+        // an E shape wound clockwise, with two notches
+        let points = [
+            vec2(0.0, 0.0),
+            vec2(0.0, 5.0),
+            vec2(3.0, 5.0),
+            vec2(3.0, 4.0),
+            vec2(1.0, 4.0),
+            vec2(1.0, 3.0),
+            vec2(3.0, 3.0),
+            vec2(3.0, 2.0),
+            vec2(1.0, 2.0),
+            vec2(1.0, 1.0),
+            vec2(3.0, 1.0),
+            vec2(3.0, 0.0),
+        ];
+        let mut mesh = Mesh::new();
+        mesh.fill(&points, Hsva::new(0.0, 1.0, 1.0, 1.0));
+        assert_eq!(mesh.indices.len(), 3 * (points.len() - 2));
+        let areas: Vec<f32> = mesh.indices.chunks(3).map(|t| area(&mesh, t)).collect();
+        assert!(areas.iter().all(|&a| a <= 0.0));
+        assert!((areas.iter().sum::<f32>() + 11.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn within_keeps_touching_triangles() {
+        let mut mesh = Mesh::new();
+        let color = Hsva::new(0.0, 1.0, 1.0, 1.0);
+        mesh.fill(&[vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)], color);
+        mesh.fill(
+            &[vec2(10.0, 10.0), vec2(11.0, 10.0), vec2(10.0, 11.0)],
+            color,
+        );
+        let part = mesh.within(Rect::from_x_y_w_h(10.0, 10.0, 2.0, 2.0));
+        assert_eq!(part.vertices.len(), 3);
+        assert_eq!(part.indices, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,518 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use nannou::prelude::*;
+
+use crate::{geometry::Geometry, mesh::triangulate};
+
+/// Rings are widened at corners to keep the tube thickness, up to this factor.
+const MAX_CORNER_SCALE: f32 = 4.0;
+
+/// How 2D turtle geometry is turned into solids.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolidSettings {
+    /// Output units per drawing unit.
+    pub scale: f32,
+    /// Vertices around each tube ring.
+    pub sides: usize,
+    /// Thickness of leaf slabs, in output units.
+    pub leaf_thickness: f32,
+}
+
+impl SolidSettings {
+    pub fn new(scale: f32, sides: usize, leaf_thickness: f32) -> Self {
+        SolidSettings {
+            scale,
+            sides,
+            leaf_thickness,
+        }
+    }
+    pub fn default() -> Self {
+        SolidSettings::new(1.0, 8, 0.5)
+    }
+}
+
+/// A triangle mesh with outward facing, counterclockwise triangles.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Solid {
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl Solid {
+    pub fn new() -> Self {
+        Solid::default()
+    }
+    /// Adds `other` as a separate shell.
+    pub fn append(&mut self, other: Solid) {
+        let base = self.vertices.len();
+        self.vertices.extend(other.vertices);
+        self.triangles
+            .extend(other.triangles.into_iter().map(|t| t.map(|i| i + base)));
+    }
+    /// Every edge is used exactly once in each direction, so each shell is closed and
+    /// consistently oriented.
+    pub fn is_watertight(&self) -> bool {
+        let mut edges: HashMap<(usize, usize), i32> = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((from, to)).or_default() += 1;
+            }
+        }
+        edges
+            .iter()
+            .all(|(&(from, to), &count)| count == 1 && edges.get(&(to, from)) == Some(&1))
+    }
+    fn triangle_normal(&self, [a, b, c]: [usize; 3]) -> Vec3 {
+        let (a, b, c) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+        (b - a).cross(c - a).normalize_or_zero()
+    }
+}
+
+/// A closed tube of `radius` along `points`, lying in the z = 0 plane. Open polylines get
+/// flat end caps, closed ones join up into a ring.
+pub fn tube(points: &[Vec2], radius: f32, sides: usize) -> Solid {
+    let mut points = points.to_vec();
+    points.dedup();
+    let closed = points.len() > 3 && points.first() == points.last();
+    if closed {
+        points.pop();
+    }
+    let mut solid = Solid::new();
+    let n = points.len();
+    let sides = sides.max(3);
+    if n < 2 || radius <= 0.0 {
+        return solid;
+    }
+
+    for i in 0..n {
+        let incoming = match i {
+            0 if !closed => points[1] - points[0],
+            _ => points[i] - points[(i + n - 1) % n],
+        }
+        .normalize();
+        let outgoing = match i {
+            i if i == n - 1 && !closed => incoming,
+            _ => points[(i + 1) % n] - points[i],
+        }
+        .normalize();
+        let tangent = (incoming + outgoing).normalize_or_zero();
+        let tangent = if tangent == Vec2::ZERO {
+            incoming
+        } else {
+            tangent
+        };
+        // stretch the ring sideways so the tube keeps its width around the corner
+        let stretch = 1.0 / tangent.dot(incoming).max(1.0 / MAX_CORNER_SCALE);
+        let side = vec3(-tangent.y, tangent.x, 0.0) * radius * stretch;
+        let up = vec3(0.0, 0.0, radius);
+        let center = points[i].extend(0.0);
+        for k in 0..sides {
+            let angle = k as f32 * TAU / sides as f32;
+            solid
+                .vertices
+                .push(center + side * angle.cos() + up * angle.sin());
+        }
+    }
+
+    let ring = |i: usize, k: usize| (i % n) * sides + k % sides;
+    let segments = if closed { n } else { n - 1 };
+    for i in 0..segments {
+        for k in 0..sides {
+            let (a, b) = (ring(i, k), ring(i, k + 1));
+            let (c, d) = (ring(i + 1, k), ring(i + 1, k + 1));
+            solid.triangles.push([a, b, c]);
+            solid.triangles.push([b, d, c]);
+        }
+    }
+    if !closed {
+        let start = solid.vertices.len();
+        solid.vertices.push(points[0].extend(0.0));
+        let end = solid.vertices.len();
+        solid.vertices.push(points[n - 1].extend(0.0));
+        for k in 0..sides {
+            solid.triangles.push([start, ring(0, k + 1), ring(0, k)]);
+            solid
+                .triangles
+                .push([end, ring(n - 1, k), ring(n - 1, k + 1)]);
+        }
+    }
+    solid
+}
+
+/// A sphere filling the joint where branches meet.
+pub fn node(center: Vec2, radius: f32, sides: usize) -> Solid {
+    let mut solid = Solid::new();
+    let sides = sides.max(3);
+    let stacks = (sides / 2).max(2);
+    let center = center.extend(0.0);
+    solid.vertices.push(center - vec3(0.0, 0.0, radius));
+    for stack in 1..stacks {
+        let polar = PI * stack as f32 / stacks as f32;
+        for k in 0..sides {
+            let angle = k as f32 * TAU / sides as f32;
+            solid.vertices.push(
+                center
+                    + vec3(
+                        polar.sin() * angle.cos(),
+                        polar.sin() * angle.sin(),
+                        -polar.cos(),
+                    ) * radius,
+            );
+        }
+    }
+    let top = solid.vertices.len();
+    solid.vertices.push(center + vec3(0.0, 0.0, radius));
+
+    let ring = |stack: usize, k: usize| 1 + (stack - 1) * sides + k % sides;
+    for k in 0..sides {
+        solid.triangles.push([0, ring(1, k + 1), ring(1, k)]);
+        solid
+            .triangles
+            .push([top, ring(stacks - 1, k), ring(stacks - 1, k + 1)]);
+    }
+    for stack in 1..stacks - 1 {
+        for k in 0..sides {
+            let (a, b) = (ring(stack, k), ring(stack, k + 1));
+            let (c, d) = (ring(stack + 1, k), ring(stack + 1, k + 1));
+            solid.triangles.push([a, b, d]);
+            solid.triangles.push([a, d, c]);
+        }
+    }
+    solid
+}
+
+/// `outline` extruded to `thickness`, centered on the z = 0 plane.
+pub fn slab(outline: &[Vec2], thickness: f32) -> Solid {
+    let mut outline = outline.to_vec();
+    outline.dedup();
+    if outline.len() > 3 && outline.first() == outline.last() {
+        outline.pop();
+    }
+    let mut solid = Solid::new();
+    let n = outline.len();
+    if n < 3 {
+        return solid;
+    }
+    // walk the outline counterclockwise so the caps face outward
+    let area: f32 = (0..n)
+        .map(|i| outline[i].perp_dot(outline[(i + 1) % n]))
+        .sum();
+    if area < 0.0 {
+        outline.reverse();
+    }
+    let half = thickness / 2.0;
+    solid
+        .vertices
+        .extend(outline.iter().map(|p| p.extend(-half)));
+    solid
+        .vertices
+        .extend(outline.iter().map(|p| p.extend(half)));
+    for [a, b, c] in triangulate(&outline) {
+        solid.triangles.push([a, c, b]);
+        solid.triangles.push([n + a, n + b, n + c]);
+    }
+    for i in 0..n {
+        let j = (i + 1) % n;
+        solid.triangles.push([i, j, n + j]);
+        solid.triangles.push([i, n + j, n + i]);
+    }
+    solid
+}
+
+/// Branches as tubes joined by spheres at their nodes, and leaves as slabs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeSolids {
+    pub wood: Solid,
+    pub leaves: Solid,
+}
+
+impl TreeSolids {
+    pub fn new(geometry: &Geometry, settings: &SolidSettings) -> Self {
+        let scale = settings.scale;
+        let mut solids = TreeSolids::default();
+        // a branch leaves its parent where an earlier polyline ended or passed through
+        let key = |p: Vec2| (p.x.to_bits(), p.y.to_bits());
+        let mut visited = HashSet::new();
+        for polyline in &geometry.polylines {
+            let points: Vec<Vec2> = polyline.points.iter().map(|&p| p * scale).collect();
+            let radius = polyline.width / 2.0 * scale;
+            solids.wood.append(tube(&points, radius, settings.sides));
+            if visited.contains(&key(polyline.points[0])) {
+                solids.wood.append(node(points[0], radius, settings.sides));
+            }
+            visited.extend(polyline.points.iter().map(|&p| key(p)));
+        }
+        for polygon in &geometry.polygons {
+            let outline: Vec<Vec2> = polygon.points.iter().map(|&p| p * scale).collect();
+            solids
+                .leaves
+                .append(slab(&outline, settings.leaf_thickness));
+        }
+        solids
+    }
+    pub fn is_watertight(&self) -> bool {
+        self.wood.is_watertight() && self.leaves.is_watertight()
+    }
+    /// Wavefront OBJ with `wood` and `leaves` groups.
+    pub fn obj(&self) -> String {
+        let mut obj = String::from("o lsystem\n");
+        let mut base = 1;
+        for (name, solid) in [("wood", &self.wood), ("leaves", &self.leaves)] {
+            writeln!(obj, "g {}", name).unwrap();
+            for v in &solid.vertices {
+                writeln!(obj, "v {:.5} {:.5} {:.5}", v.x, v.y, v.z).unwrap();
+            }
+            for [a, b, c] in &solid.triangles {
+                writeln!(obj, "f {} {} {}", a + base, b + base, c + base).unwrap();
+            }
+            base += solid.vertices.len();
+        }
+        obj
+    }
+    /// Binary STL holding both groups.
+    pub fn stl(&self) -> Vec<u8> {
+        let count: usize = self.wood.triangles.len() + self.leaves.triangles.len();
+        let mut stl = Vec::with_capacity(84 + count * 50);
+        let mut header = [b' '; 80];
+        let title = b"l-systems binary stl";
+        header[..title.len()].copy_from_slice(title);
+        stl.extend_from_slice(&header);
+        stl.extend_from_slice(&(count as u32).to_le_bytes());
+        for solid in [&self.wood, &self.leaves] {
+            for &triangle in &solid.triangles {
+                let normal = solid.triangle_normal(triangle);
+                let corners = triangle.map(|i| solid.vertices[i]);
+                for v in [normal, corners[0], corners[1], corners[2]] {
+                    for c in [v.x, v.y, v.z] {
+                        stl.extend_from_slice(&c.to_le_bytes());
+                    }
+                }
+                stl.extend_from_slice(&0u16.to_le_bytes());
+            }
+        }
+        stl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystems::SegmentMeta;
+
+    /// How many times the surface winds around `p`, 1 inside a closed outward facing shell
+    /// and 0 outside, from the solid angles of the triangles.
+    fn winding_number(solid: &Solid, p: Vec3) -> f32 {
+        let total: f32 = solid
+            .triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| solid.vertices[i] - p);
+                let (la, lb, lc) = (a.length(), b.length(), c.length());
+                let numerator = a.dot(b.cross(c));
+                let denominator = la * lb * lc + a.dot(b) * lc + b.dot(c) * la + c.dot(a) * lb;
+                2.0 * numerator.atan2(denominator)
+            })
+            .sum();
+        total / (4.0 * PI)
+    }
+
+    /// Whether crossing every face from its back to its front leaves one layer of the
+    /// solid, which holds even where shells overlap. A solid turned inside out winds
+    /// negatively, so the front must not.
+    fn faces_point_outward(solid: &Solid) -> bool {
+        !solid.triangles.is_empty()
+            && solid.triangles.iter().all(|&triangle| {
+                let corners = triangle.map(|i| solid.vertices[i]);
+                let center = (corners[0] + corners[1] + corners[2]) / 3.0;
+                let step = solid.triangle_normal(triangle) * 1e-3;
+                let back = winding_number(solid, center - step);
+                let front = winding_number(solid, center + step);
+                (back - front - 1.0).abs() < 1e-2 && front > -1e-2
+            })
+    }
+
+    fn assert_closed_solid(solid: &Solid) {
+        assert!(solid.is_watertight());
+        assert!(faces_point_outward(solid));
+    }
+
+    #[test]
+    fn straight_tube() {
+        let solid = tube(&[vec2(0.0, 0.0), vec2(10.0, 0.0)], 1.0, 8);
+        assert_closed_solid(&solid);
+        // two rings and the cap centers, the side quads and both cap fans
+        assert_eq!(solid.vertices.len(), 2 * 8 + 2);
+        assert_eq!(solid.triangles.len(), 2 * 8 + 2 * 8);
+    }
+
+    #[test]
+    fn bent_tube() {
+        let points = [
+            vec2(0.0, 0.0),
+            vec2(10.0, 0.0),
+            vec2(10.0, 10.0),
+            vec2(3.0, 14.0),
+        ];
+        let solid = tube(&points, 1.0, 6);
+        assert_closed_solid(&solid);
+        assert_eq!(solid.triangles.len(), 3 * 2 * 6 + 2 * 6);
+    }
+
+    #[test]
+    fn closed_tube_is_a_ring() {
+        let square = [
+            vec2(0.0, 0.0),
+            vec2(10.0, 0.0),
+            vec2(10.0, 10.0),
+            vec2(0.0, 10.0),
+            vec2(0.0, 0.0),
+        ];
+        let solid = tube(&square, 1.0, 8);
+        assert_closed_solid(&solid);
+        // no caps
+        assert_eq!(solid.vertices.len(), 4 * 8);
+        assert_eq!(solid.triangles.len(), 4 * 2 * 8);
+    }
+
+    #[test]
+    fn inside_out_solids_fail() {
+        let mut solid = node(vec2(0.0, 0.0), 1.0, 6);
+        solid.triangles.iter_mut().for_each(|t| t.swap(1, 2));
+        assert!(solid.is_watertight());
+        assert!(!faces_point_outward(&solid));
+    }
+
+    #[test]
+    fn degenerate_tubes_are_empty() {
+        assert_eq!(
+            tube(&[vec2(1.0, 1.0), vec2(1.0, 1.0)], 1.0, 8),
+            Solid::new()
+        );
+        assert_eq!(
+            tube(&[vec2(0.0, 0.0), vec2(1.0, 0.0)], 0.0, 8),
+            Solid::new()
+        );
+    }
+
+    #[test]
+    fn node_sphere() {
+        let solid = node(vec2(3.0, -2.0), 2.0, 8);
+        assert_closed_solid(&solid);
+        // two poles and three rings between them
+        assert_eq!(solid.vertices.len(), 2 + 3 * 8);
+        assert_eq!(solid.triangles.len(), 2 * 8 * 3);
+        for v in &solid.vertices {
+            assert!((v.distance(vec3(3.0, -2.0, 0.0)) - 2.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn slab_of_either_winding() {
+        // a concave leaf, given clockwise
+        let outline = [
+            vec2(0.0, 0.0),
+            vec2(-1.0, 3.0),
+            vec2(0.0, 2.0),
+            vec2(1.0, 3.0),
+        ];
+        for outline in [outline.to_vec(), outline.iter().rev().copied().collect()] {
+            let solid = slab(&outline, 0.5);
+            assert_closed_solid(&solid);
+            assert_eq!(solid.vertices.len(), 2 * 4);
+            assert_eq!(solid.triangles.len(), 2 * 2 + 2 * 4);
+            assert!(solid.vertices.iter().all(|v| v.z.abs() == 0.25));
+        }
+    }
+
+    /// A trunk with one branch leaving it halfway up, and a leaf at the branch tip.
+    fn small_tree() -> Geometry {
+        let meta = SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index: 0,
+            arc_length: 0.0,
+            branch_id: 0,
+        };
+        let brown = Hsv::new(30.0, 0.5, 0.5);
+        let mut geometry = Geometry::new();
+        geometry.line(vec2(0.0, 0.0), vec2(0.0, 10.0), brown, 2.0, meta);
+        geometry.line(vec2(0.0, 10.0), vec2(0.0, 20.0), brown, 2.0, meta);
+        geometry.line(vec2(0.0, 10.0), vec2(6.0, 16.0), brown, 1.0, meta);
+        geometry.polygon(
+            vec![
+                vec2(6.0, 16.0),
+                vec2(8.0, 17.0),
+                vec2(9.0, 19.0),
+                vec2(7.0, 18.0),
+            ],
+            Hsv::new(120.0, 1.0, 0.5),
+        );
+        geometry
+    }
+
+    #[test]
+    fn tree_solids() {
+        let settings = SolidSettings::new(2.0, 8, 0.5);
+        let solids = TreeSolids::new(&small_tree(), &settings);
+        assert!(solids.is_watertight());
+        assert!(faces_point_outward(&solids.wood));
+        assert!(faces_point_outward(&solids.leaves));
+
+        // the trunk and branch tubes, and the sphere where the branch leaves the trunk
+        let trunk = 2 * 2 * 8 + 2 * 8;
+        let branch = 2 * 8 + 2 * 8;
+        let sphere = 2 * 8 * 3;
+        assert_eq!(solids.wood.triangles.len(), trunk + branch + sphere);
+        assert_eq!(solids.leaves.triangles.len(), 2 * 2 + 2 * 4);
+        // scaled to output units
+        let top = solids
+            .wood
+            .vertices
+            .iter()
+            .map(|v| v.y)
+            .fold(f32::MIN, f32::max);
+        assert!((top - 40.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn obj_and_stl_triangle_counts() {
+        let solids = TreeSolids::new(&small_tree(), &SolidSettings::default());
+        let triangles = solids.wood.triangles.len() + solids.leaves.triangles.len();
+        let vertices = solids.wood.vertices.len() + solids.leaves.vertices.len();
+
+        let obj = solids.obj();
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!(count("f "), triangles);
+        assert_eq!(count("v "), vertices);
+        assert_eq!(count("g "), 2);
+        // indices are 1 based and refer to vertices already written
+        let mut written = 0;
+        for line in obj.lines() {
+            if line.starts_with("v ") {
+                written += 1;
+            } else if let Some(face) = line.strip_prefix("f ") {
+                for index in face.split(' ') {
+                    let index: usize = index.parse().unwrap();
+                    assert!(index >= 1 && index <= written);
+                }
+            }
+        }
+
+        let stl = solids.stl();
+        assert_eq!(stl.len(), 84 + 50 * triangles);
+        let header_count = u32::from_le_bytes(stl[80..84].try_into().unwrap());
+        assert_eq!(header_count as usize, triangles);
+        // each record starts with a unit normal
+        for record in stl[84..].chunks_exact(50) {
+            let normal: Vec<f32> = record[..12]
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect();
+            let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+            assert!((length - 1.0).abs() < 1e-4);
+        }
+    }
+}
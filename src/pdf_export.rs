@@ -0,0 +1,433 @@
+use std::fmt::Write;
+
+use nannou::prelude::*;
+
+use crate::{
+    color_palette::srgb_bytes,
+    geometry::Geometry,
+    stroke::{LineCap, LineJoin, StrokeStyle},
+};
+
+/// PostScript points per millimetre.
+pub const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageSize {
+    A4,
+    A3,
+    Letter,
+    Custom,
+}
+
+impl PageSize {
+    pub const ALL: [PageSize; 4] = [
+        PageSize::A4,
+        PageSize::A3,
+        PageSize::Letter,
+        PageSize::Custom,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PageSize::A4 => "A4",
+            PageSize::A3 => "A3",
+            PageSize::Letter => "Letter",
+            PageSize::Custom => "Custom",
+        }
+    }
+}
+
+/// Where the drawing goes on the printed page. Lengths are in millimetres.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageLayout {
+    pub size: PageSize,
+    /// Portrait width and height used by `PageSize::Custom`.
+    pub custom: Vec2,
+    pub landscape: bool,
+    pub margin: f32,
+    /// Scale the drawing to fill the page inside the margins. Otherwise one drawing unit
+    /// is one point and the drawing is centered.
+    pub fit: bool,
+}
+
+impl PageLayout {
+    pub fn new(size: PageSize, landscape: bool, margin: f32, fit: bool) -> Self {
+        PageLayout {
+            size,
+            custom: vec2(500.0, 700.0),
+            landscape,
+            margin,
+            fit,
+        }
+    }
+    pub fn default() -> Self {
+        PageLayout::new(PageSize::A4, false, 10.0, true)
+    }
+    /// Page width and height in points.
+    pub fn page_points(&self) -> Vec2 {
+        let portrait = match self.size {
+            PageSize::A4 => vec2(210.0, 297.0),
+            PageSize::A3 => vec2(297.0, 420.0),
+            PageSize::Letter => vec2(215.9, 279.4),
+            PageSize::Custom => self.custom,
+        } * POINTS_PER_MM;
+        if self.landscape {
+            vec2(portrait.y, portrait.x)
+        } else {
+            portrait
+        }
+    }
+    /// Scale and offset taking drawing coordinates to page points.
+    pub fn transform(&self, bounds: Rect) -> (f32, Vec2) {
+        let page = self.page_points();
+        let margin = self.margin * POINTS_PER_MM;
+        let area = vec2(
+            (page.x - 2.0 * margin).max(0.0),
+            (page.y - 2.0 * margin).max(0.0),
+        );
+        let scale = if !self.fit {
+            1.0
+        } else {
+            match (bounds.w() > 0.0, bounds.h() > 0.0) {
+                (true, true) => (area.x / bounds.w()).min(area.y / bounds.h()),
+                (true, false) => area.x / bounds.w(),
+                (false, true) => area.y / bounds.h(),
+                (false, false) => 1.0,
+            }
+        };
+        (scale, page / 2.0 - bounds.xy() * scale)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dialect {
+    Pdf,
+    PostScript,
+}
+
+/// Builds page drawing operators in PDF content stream or PostScript syntax, which share
+/// a coordinate system and differ mostly in operator names.
+struct Painter {
+    dialect: Dialect,
+    scale: f32,
+    offset: Vec2,
+    out: String,
+}
+
+impl Painter {
+    fn point(&self, p: Vec2) -> Vec2 {
+        p * self.scale + self.offset
+    }
+    fn op(&mut self, operands: &str, pdf: &str, postscript: &str) {
+        let name = match self.dialect {
+            Dialect::Pdf => pdf,
+            Dialect::PostScript => postscript,
+        };
+        if operands.is_empty() {
+            writeln!(self.out, "{}", name).unwrap();
+        } else {
+            writeln!(self.out, "{} {}", operands, name).unwrap();
+        }
+    }
+    fn style(&mut self, stroke: &StrokeStyle) {
+        let cap = match stroke.cap {
+            LineCap::Butt => 0,
+            LineCap::Round => 1,
+            LineCap::Square => 2,
+        };
+        let join = match stroke.join {
+            LineJoin::Miter => 0,
+            LineJoin::Round => 1,
+            LineJoin::Bevel => 2,
+        };
+        self.op(&cap.to_string(), "J", "setlinecap");
+        self.op(&join.to_string(), "j", "setlinejoin");
+        if !stroke.dashes.is_empty() {
+            let dashes: Vec<String> = stroke
+                .dashes
+                .iter()
+                .map(|d| format!("{:.3}", d * self.scale))
+                .collect();
+            let operands = format!(
+                "[{}] {:.3}",
+                dashes.join(" "),
+                stroke.dash_offset * self.scale
+            );
+            self.op(&operands, "d", "setdash");
+        }
+    }
+    fn color(&mut self, color: Hsv, fill: bool) {
+        let [r, g, b] = srgb_bytes(color).map(|c| c as f32 / 255.0);
+        let operands = format!("{:.3} {:.3} {:.3}", r, g, b);
+        if fill {
+            self.op(&operands, "rg", "setrgbcolor");
+        } else {
+            self.op(&operands, "RG", "setrgbcolor");
+        }
+    }
+    fn path(&mut self, points: &[Vec2], close: bool) {
+        if self.dialect == Dialect::PostScript {
+            self.op("", "", "newpath");
+        }
+        for (i, &p) in points.iter().enumerate() {
+            let p = self.point(p);
+            let operands = format!("{:.3} {:.3}", p.x, p.y);
+            if i == 0 {
+                self.op(&operands, "m", "moveto");
+            } else {
+                self.op(&operands, "l", "lineto");
+            }
+        }
+        if close {
+            self.op("", "h", "closepath");
+        }
+    }
+    fn draw(&mut self, geometry: &Geometry, stroke: &StrokeStyle) {
+        self.style(stroke);
+        for polyline in &geometry.polylines {
+            let width = format!("{:.3}", polyline.width * self.scale);
+            self.op(&width, "w", "setlinewidth");
+            for (color, points) in polyline.color_runs() {
+                self.color(color, false);
+                self.path(&points, false);
+                self.op("", "S", "stroke");
+            }
+        }
+        for polygon in &geometry.polygons {
+            self.color(polygon.color, true);
+            self.path(&polygon.points, true);
+            self.op("", "f", "fill");
+        }
+        for point in &geometry.points {
+            let circle: Vec<Vec2> = (0..32)
+                .map(|i| point.pos + vec2(point.radius, 0.0).rotate(i as f32 * TAU / 32.0))
+                .collect();
+            self.color(point.color, true);
+            self.path(&circle, true);
+            self.op("", "f", "fill");
+        }
+    }
+}
+
+fn paint(
+    dialect: Dialect,
+    geometry: &Geometry,
+    stroke: &StrokeStyle,
+    layout: &PageLayout,
+) -> String {
+    let bounds = geometry.bounds().unwrap_or(Rect::from_w_h(0.0, 0.0));
+    let (scale, offset) = layout.transform(bounds);
+    let mut painter = Painter {
+        dialect,
+        scale,
+        offset,
+        out: String::new(),
+    };
+    painter.draw(geometry, stroke);
+    painter.out
+}
+
+/// A single page PDF with the geometry as vector paths, widths and dashes scaled with the
+/// drawing. Glow needs transparency, which print output leaves out.
+pub fn geometry_pdf(geometry: &Geometry, stroke: &StrokeStyle, layout: &PageLayout) -> Vec<u8> {
+    let page = layout.page_points();
+    let content = paint(Dialect::Pdf, geometry, stroke, layout);
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.3} {:.3}] /Resources << >> /Contents 4 0 R >>",
+            page.x, page.y
+        ),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+    ];
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        write!(pdf, "{} 0 obj\n{}\nendobj\n", i + 1, object).unwrap();
+    }
+    let xref = pdf.len();
+    write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+    for offset in offsets {
+        write!(pdf, "{:010} 00000 n \n", offset).unwrap();
+    }
+    write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    )
+    .unwrap();
+    pdf.into_bytes()
+}
+
+/// An Encapsulated PostScript file the size of the page, drawn like `geometry_pdf`.
+pub fn geometry_eps(geometry: &Geometry, stroke: &StrokeStyle, layout: &PageLayout) -> String {
+    let page = layout.page_points();
+    let mut eps = String::from("%!PS-Adobe-3.0 EPSF-3.0\n");
+    writeln!(
+        eps,
+        "%%BoundingBox: 0 0 {} {}",
+        page.x.ceil(),
+        page.y.ceil()
+    )
+    .unwrap();
+    writeln!(eps, "%%HiResBoundingBox: 0 0 {:.3} {:.3}", page.x, page.y).unwrap();
+    writeln!(eps, "%%Creator: l-systems").unwrap();
+    writeln!(eps, "%%EndComments").unwrap();
+    eps.push_str(&paint(Dialect::PostScript, geometry, stroke, layout));
+    writeln!(eps, "showpage").unwrap();
+    writeln!(eps, "%%EOF").unwrap();
+    eps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentMeta;
+
+    fn line() -> Geometry {
+        let meta = SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index: 0,
+            arc_length: 0.0,
+            branch_id: 0,
+        };
+        let mut geometry = Geometry::new();
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        geometry.line(vec2(-50.0, 0.0), vec2(50.0, 50.0), color, 1.0, meta);
+        geometry
+    }
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 0.01, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn page_sizes_in_points() {
+        let size = |size| PageLayout::new(size, false, 0.0, true).page_points();
+        assert_close(size(PageSize::A4), vec2(595.28, 841.89));
+        assert_close(size(PageSize::A3), vec2(841.89, 1190.55));
+        assert_close(size(PageSize::Letter), vec2(612.0, 792.0));
+        let custom = PageLayout {
+            custom: vec2(100.0, 200.0),
+            ..PageLayout::new(PageSize::Custom, false, 0.0, true)
+        };
+        assert_close(custom.page_points(), vec2(283.46, 566.93));
+    }
+
+    #[test]
+    fn landscape_swaps_the_sides() {
+        for size in PageSize::ALL {
+            let portrait = PageLayout::new(size, false, 0.0, true).page_points();
+            let landscape = PageLayout::new(size, true, 0.0, true).page_points();
+            assert_eq!(landscape, vec2(portrait.y, portrait.x));
+        }
+    }
+
+    #[test]
+    fn fit_fills_the_page_inside_the_margins() {
+        let layout = PageLayout::new(PageSize::A4, false, 10.0, true);
+        let bounds = Rect::from_x_y_w_h(0.0, 25.0, 100.0, 50.0);
+        let (scale, offset) = layout.transform(bounds);
+        let page = layout.page_points();
+        let margin = 10.0 * POINTS_PER_MM;
+        // the width is what limits the drawing on a portrait page
+        assert!(
+            (scale - (page.x - 2.0 * margin) / 100.0).abs() < 1e-4,
+            "{}",
+            scale
+        );
+        let map = |p: Vec2| p * scale + offset;
+        assert_close(
+            map(vec2(bounds.left(), bounds.bottom())),
+            vec2(margin, page.y / 2.0 - 25.0 * scale),
+        );
+        assert_close(
+            map(vec2(bounds.right(), bounds.top())),
+            vec2(page.x - margin, page.y / 2.0 + 25.0 * scale),
+        );
+
+        // a flat drawing is fitted along the side it has
+        let flat = Rect::from_x_y_w_h(0.0, 0.0, 0.0, 100.0);
+        let (scale, _) = layout.transform(flat);
+        assert!(
+            (scale - (page.y - 2.0 * margin) / 100.0).abs() < 1e-4,
+            "{}",
+            scale
+        );
+    }
+
+    #[test]
+    fn unfitted_drawings_are_centered_at_one_point_per_unit() {
+        let layout = PageLayout::new(PageSize::Letter, true, 10.0, false);
+        let bounds = Rect::from_x_y_w_h(10.0, 20.0, 100.0, 50.0);
+        let (scale, offset) = layout.transform(bounds);
+        assert_eq!(scale, 1.0);
+        assert_close(bounds.xy() + offset, layout.page_points() / 2.0);
+    }
+
+    #[test]
+    fn pdf_offsets_and_lengths_are_exact() {
+        let layout = PageLayout::default();
+        let pdf = geometry_pdf(&line(), &StrokeStyle::default(), &layout);
+        let text = String::from_utf8(pdf).unwrap();
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+
+        // startxref points at the table, and every entry at its object
+        let startxref: usize = text
+            .split("startxref\n")
+            .nth(1)
+            .and_then(|rest| rest.lines().next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(text[startxref..].starts_with("xref\n0 5\n"));
+        let entries: Vec<usize> = text[startxref..]
+            .lines()
+            .skip(3)
+            .take(4)
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+        for (i, offset) in entries.iter().enumerate() {
+            assert!(
+                text[*offset..].starts_with(&format!("{} 0 obj\n", i + 1)),
+                "object {}",
+                i + 1
+            );
+        }
+        assert!(text.contains("/Size 5 /Root 1 0 R"));
+
+        // the stream is exactly as long as it says
+        let length: usize = text
+            .split("/Length ")
+            .nth(1)
+            .and_then(|rest| rest.split(' ').next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let start = text.find("stream\n").unwrap() + "stream\n".len();
+        let end = text.find("endstream").unwrap();
+        assert_eq!(end - start, length);
+        assert!(text[start..end].contains(" m\n") && text[start..end].contains("\nS\n"));
+
+        let page = layout.page_points();
+        assert!(text.contains(&format!("/MediaBox [0 0 {:.3} {:.3}]", page.x, page.y)));
+    }
+
+    #[test]
+    fn eps_bounding_box_covers_the_page() {
+        let layout = PageLayout::new(PageSize::A4, true, 10.0, true);
+        let eps = geometry_eps(&line(), &StrokeStyle::default(), &layout);
+        let lines: Vec<&str> = eps.lines().collect();
+        assert_eq!(lines[0], "%!PS-Adobe-3.0 EPSF-3.0");
+        assert_eq!(lines[1], "%%BoundingBox: 0 0 842 596");
+        assert_eq!(lines[2], "%%HiResBoundingBox: 0 0 841.890 595.276");
+        assert!(eps.contains("moveto") && eps.contains("stroke"));
+        assert!(eps.ends_with("showpage\n%%EOF\n"));
+    }
+}
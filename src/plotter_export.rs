@@ -0,0 +1,406 @@
+use std::fmt::Write;
+
+use nannou::prelude::*;
+
+use crate::{
+    geometry::Geometry,
+    pdf_export::{PageLayout, POINTS_PER_MM},
+};
+
+/// Travel shorter than this, in millimetres, is drawn with the pen down.
+const JOIN_DISTANCE: f32 = 0.01;
+/// 2-opt passes over the path order before settling for what it has.
+const MAX_TWO_OPT_PASSES: usize = 8;
+/// How many following paths 2-opt tries to reverse up to, which keeps it fast on
+/// drawings with thousands of branches.
+const TWO_OPT_WINDOW: usize = 256;
+
+/// Machine settings for pen plotters. Lengths are in millimetres, speeds in mm/s.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotterSettings {
+    pub draw_speed: f32,
+    pub travel_speed: f32,
+    /// Time to raise or lower the pen, in seconds.
+    pub pen_delay: f32,
+    /// G-code lines that raise and lower the pen.
+    pub pen_up: String,
+    pub pen_down: String,
+}
+
+impl PlotterSettings {
+    pub fn new(draw_speed: f32, travel_speed: f32, pen_delay: f32) -> Self {
+        PlotterSettings {
+            draw_speed,
+            travel_speed,
+            pen_delay,
+            pen_up: "G0 Z2".to_string(),
+            pen_down: "G1 Z0".to_string(),
+        }
+    }
+    pub fn default() -> Self {
+        PlotterSettings::new(25.0, 75.0, 0.15)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlotStats {
+    pub draw_distance: f32,
+    pub travel_distance: f32,
+    /// Times the pen is raised to travel between strokes. The pen starts raised, so the
+    /// travel to the first stroke and the final raise are not lifts.
+    pub pen_lifts: usize,
+    /// Estimated plot time in seconds.
+    pub time: f32,
+}
+
+impl PlotStats {
+    pub fn summary(&self) -> String {
+        let seconds = self.time.round() as u32;
+        format!(
+            "{:.0} mm drawn, {:.0} mm travel, {} pen lifts, about {}h {:02}m {:02}s",
+            self.draw_distance,
+            self.travel_distance,
+            self.pen_lifts,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Strokes placed on the page in millimetres, with the origin at the bottom left, in the
+/// order the pen draws them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotPlan {
+    pub paths: Vec<Vec<Vec2>>,
+}
+
+impl PlotPlan {
+    /// Lays the polylines and polygon outlines of `geometry` out on `page` and orders
+    /// them to keep pen-up travel short. Colors are ignored, the plotter has one pen.
+    pub fn new(geometry: &Geometry, page: &PageLayout) -> Self {
+        let bounds = geometry.bounds().unwrap_or(Rect::from_w_h(0.0, 0.0));
+        let (scale, offset) = page.transform(bounds);
+        let to_mm = |p: Vec2| (p * scale + offset) / POINTS_PER_MM;
+
+        let outlines = geometry.polygons.iter().map(|polygon| {
+            let mut points = polygon.points.clone();
+            points.extend(polygon.points.first());
+            points
+        });
+        let paths = geometry
+            .polylines
+            .iter()
+            .map(|polyline| polyline.points.clone())
+            .chain(outlines)
+            .map(|points| {
+                let mut points: Vec<Vec2> = points.into_iter().map(to_mm).collect();
+                points.dedup();
+                points
+            })
+            .filter(|points| points.len() > 1)
+            .collect();
+
+        let mut plan = PlotPlan { paths };
+        plan.order_nearest_neighbor();
+        plan.two_opt();
+        plan
+    }
+    /// Greedily draws whichever path end is nearest next, reversing paths as needed.
+    pub fn order_nearest_neighbor(&mut self) {
+        let mut remaining = std::mem::take(&mut self.paths);
+        let mut pos = Vec2::ZERO;
+        while !remaining.is_empty() {
+            let (index, reverse, _) = remaining
+                .iter()
+                .enumerate()
+                .flat_map(|(i, path)| {
+                    [
+                        (i, false, pos.distance_squared(path[0])),
+                        (i, true, pos.distance_squared(*path.last().unwrap())),
+                    ]
+                })
+                .fold((0, false, f32::MAX), |best, candidate| {
+                    if candidate.2 < best.2 {
+                        candidate
+                    } else {
+                        best
+                    }
+                });
+            let mut path = remaining.swap_remove(index);
+            if reverse {
+                path.reverse();
+            }
+            pos = *path.last().unwrap();
+            self.paths.push(path);
+        }
+    }
+    /// Reverses runs of paths, order and direction, while that shortens the travel.
+    pub fn two_opt(&mut self) {
+        let n = self.paths.len();
+        let start = |paths: &[Vec<Vec2>], i: usize| paths[i][0];
+        let end = |paths: &[Vec<Vec2>], i: usize| *paths[i].last().unwrap();
+        for _ in 0..MAX_TWO_OPT_PASSES {
+            let mut improved = false;
+            for i in 0..n {
+                let before = if i == 0 {
+                    Vec2::ZERO
+                } else {
+                    end(&self.paths, i - 1)
+                };
+                for j in i + 1..n.min(i + TWO_OPT_WINDOW) {
+                    let first = start(&self.paths, i);
+                    let last = end(&self.paths, j);
+                    let (current, reversed) = match self.paths.get(j + 1) {
+                        Some(next) => (
+                            before.distance(first) + last.distance(next[0]),
+                            before.distance(last) + first.distance(next[0]),
+                        ),
+                        None => (before.distance(first), before.distance(last)),
+                    };
+                    if reversed + 1e-3 < current {
+                        self.paths[i..=j].reverse();
+                        self.paths[i..=j].iter_mut().for_each(|path| path.reverse());
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+    pub fn stats(&self, settings: &PlotterSettings) -> PlotStats {
+        let mut stats = PlotStats {
+            draw_distance: 0.0,
+            travel_distance: 0.0,
+            pen_lifts: 0,
+            time: 0.0,
+        };
+        // every raise and lower of the pen costs a delay, like the commands `gcode` writes
+        let mut down = false;
+        let mut pen_moves = 0;
+        self.walk(|pen_down, from, to| {
+            let distance = from.distance(to);
+            if pen_down != down {
+                pen_moves += 1;
+                if !pen_down {
+                    stats.pen_lifts += 1;
+                }
+                down = pen_down;
+            }
+            if pen_down {
+                stats.draw_distance += distance;
+            } else {
+                stats.travel_distance += distance;
+            }
+        });
+        if down {
+            pen_moves += 1;
+        }
+        stats.time = stats.draw_distance / settings.draw_speed.max(f32::EPSILON)
+            + stats.travel_distance / settings.travel_speed.max(f32::EPSILON)
+            + pen_moves as f32 * settings.pen_delay;
+        stats
+    }
+    /// Visits every move in plot order, joining paths that start where the last ended.
+    fn walk(&self, mut visit: impl FnMut(bool, Vec2, Vec2)) {
+        let mut pos = Vec2::ZERO;
+        for path in &self.paths {
+            if pos.distance(path[0]) > JOIN_DISTANCE {
+                visit(false, pos, path[0]);
+            }
+            for pair in path.windows(2) {
+                visit(true, pair[0], pair[1]);
+            }
+            pos = *path.last().unwrap();
+        }
+    }
+    pub fn gcode(&self, settings: &PlotterSettings) -> String {
+        let mut gcode = String::new();
+        writeln!(gcode, "; {}", self.stats(settings).summary()).unwrap();
+        writeln!(gcode, "G21 ; millimetres").unwrap();
+        writeln!(gcode, "G90 ; absolute positions").unwrap();
+        writeln!(gcode, "{}", settings.pen_up).unwrap();
+        let (draw_feed, travel_feed) = (settings.draw_speed * 60.0, settings.travel_speed * 60.0);
+        let mut down = false;
+        self.walk(|pen_down, _, to| {
+            if pen_down != down {
+                let command = if pen_down {
+                    &settings.pen_down
+                } else {
+                    &settings.pen_up
+                };
+                writeln!(gcode, "{}", command).unwrap();
+                down = pen_down;
+            }
+            let (command, feed) = if pen_down {
+                ("G1", draw_feed)
+            } else {
+                ("G0", travel_feed)
+            };
+            writeln!(gcode, "{} X{:.3} Y{:.3} F{:.0}", command, to.x, to.y, feed).unwrap();
+        });
+        writeln!(gcode, "{}", settings.pen_up).unwrap();
+        writeln!(gcode, "G0 X0 Y0").unwrap();
+        gcode
+    }
+    /// HPGL in plotter units of 0.025 mm.
+    pub fn hpgl(&self) -> String {
+        let unit = |p: Vec2| ((p.x * 40.0).round() as i32, (p.y * 40.0).round() as i32);
+        let mut hpgl = String::from("IN;SP1;PU0,0");
+        let mut down = false;
+        self.walk(|pen_down, _, to| {
+            let (x, y) = unit(to);
+            match (pen_down, down) {
+                (true, true) | (false, false) => write!(hpgl, ",{},{}", x, y).unwrap(),
+                (true, false) => write!(hpgl, ";PD{},{}", x, y).unwrap(),
+                (false, true) => write!(hpgl, ";PU{},{}", x, y).unwrap(),
+            }
+            down = pen_down;
+        });
+        hpgl.push_str(";PU0,0;SP0;\n");
+        hpgl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(paths: &[&[(f32, f32)]]) -> PlotPlan {
+        PlotPlan {
+            paths: paths
+                .iter()
+                .map(|path| path.iter().map(|&(x, y)| vec2(x, y)).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn nearest_neighbor_reverses_paths_to_start_close() {
+        let mut ordered = plan(&[
+            &[(10.0, 0.0), (20.0, 0.0)],
+            &[(30.0, 0.0), (21.0, 0.0)],
+            &[(0.0, 1.0), (5.0, 1.0)],
+        ]);
+        ordered.order_nearest_neighbor();
+        assert_eq!(
+            ordered,
+            plan(&[
+                &[(0.0, 1.0), (5.0, 1.0)],
+                &[(10.0, 0.0), (20.0, 0.0)],
+                &[(21.0, 0.0), (30.0, 0.0)],
+            ])
+        );
+    }
+
+    #[test]
+    fn two_opt_uncrosses_travel() {
+        // the travel from the first stroke to the far one and back crosses itself
+        let mut crossing = plan(&[
+            &[(0.0, 0.0), (1.0, 0.0)],
+            &[(10.0, 10.0), (11.0, 10.0)],
+            &[(2.0, 0.0), (3.0, 0.0)],
+        ]);
+        let settings = PlotterSettings::default();
+        let before = crossing.stats(&settings).travel_distance;
+        crossing.two_opt();
+        let after = crossing.stats(&settings).travel_distance;
+        assert!(after < before, "{} >= {}", after, before);
+        // the last two strokes are drawn in reverse order and direction
+        assert_eq!(
+            crossing,
+            plan(&[
+                &[(0.0, 0.0), (1.0, 0.0)],
+                &[(3.0, 0.0), (2.0, 0.0)],
+                &[(11.0, 10.0), (10.0, 10.0)],
+            ])
+        );
+        assert!(
+            (after - 2.0 - vec2(9.0, 10.0).length()).abs() < 1e-4,
+            "{}",
+            after
+        );
+    }
+
+    #[test]
+    fn only_travel_between_strokes_lifts_the_pen() {
+        let settings = PlotterSettings::default();
+        let joined = plan(&[&[(5.0, 0.0), (6.0, 0.0)], &[(6.0, 0.0), (6.0, 1.0)]]);
+        assert_eq!(joined.stats(&settings).pen_lifts, 0);
+        let apart = plan(&[
+            &[(5.0, 0.0), (6.0, 0.0)],
+            &[(8.0, 0.0), (9.0, 0.0)],
+            &[(9.0, 5.0), (9.0, 6.0)],
+        ]);
+        assert_eq!(apart.stats(&settings).pen_lifts, 2);
+        assert_eq!(plan(&[]).stats(&settings).pen_lifts, 0);
+    }
+
+    #[test]
+    fn time_adds_drawing_travel_and_pen_moves() {
+        let settings = PlotterSettings::new(10.0, 15.0, 0.5);
+        // 15 mm of travel, 10 mm drawn, then 15 mm of travel and 10 mm drawn
+        let two = plan(&[&[(0.0, 15.0), (10.0, 15.0)], &[(10.0, 0.0), (0.0, 0.0)]]);
+        let stats = two.stats(&settings);
+        assert_eq!(stats.draw_distance, 20.0);
+        assert_eq!(stats.travel_distance, 30.0);
+        assert_eq!(stats.pen_lifts, 1);
+        // down, up, down, up
+        assert!(
+            (stats.time - (2.0 + 2.0 + 4.0 * 0.5)).abs() < 1e-5,
+            "{}",
+            stats.time
+        );
+        assert!(
+            stats.summary().ends_with("about 0h 00m 06s"),
+            "{}",
+            stats.summary()
+        );
+    }
+
+    #[test]
+    fn gcode_raises_the_pen_between_strokes() {
+        let settings = PlotterSettings::new(10.0, 20.0, 0.1);
+        let two = plan(&[
+            &[(1.0, 0.0), (2.0, 0.0)],
+            &[(2.0, 0.0), (2.0, 1.0)],
+            &[(5.0, 5.0), (6.0, 5.0)],
+        ]);
+        let gcode = two.gcode(&settings);
+        let lines: Vec<&str> = gcode.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "G21 ; millimetres",
+                "G90 ; absolute positions",
+                "G0 Z2",
+                "G0 X1.000 Y0.000 F1200",
+                "G1 Z0",
+                "G1 X2.000 Y0.000 F600",
+                "G1 X2.000 Y1.000 F600",
+                "G0 Z2",
+                "G0 X5.000 Y5.000 F1200",
+                "G1 Z0",
+                "G1 X6.000 Y5.000 F600",
+                "G0 Z2",
+                "G0 X0 Y0",
+            ]
+        );
+        assert!(gcode.starts_with("; "));
+    }
+
+    #[test]
+    fn hpgl_raises_the_pen_between_strokes() {
+        let two = plan(&[
+            &[(1.0, 0.0), (2.0, 0.0)],
+            &[(2.0, 0.0), (2.0, 1.0)],
+            &[(5.0, 5.0), (6.0, 5.0)],
+        ]);
+        assert_eq!(
+            two.hpgl(),
+            "IN;SP1;PU0,0,40,0;PD80,0,80,40;PU200,200;PD240,200;PU0,0;SP0;\n"
+        );
+    }
+}
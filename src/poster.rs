@@ -0,0 +1,192 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use nannou::prelude::*;
+
+use crate::{
+    animation::frame_transform, export::ExportError, geometry::Geometry, mesh::Mesh,
+    raster::Raster, stroke::StrokeStyle,
+};
+
+/// Pixel density stroke widths are measured at, that of an ordinary screen.
+pub const SCREEN_DPI: f32 = 96.0;
+const MM_PER_INCH: f32 = 25.4;
+
+/// A print sized image rendered one tile at a time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PosterSettings {
+    /// Image size in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Print resolution. Stroke widths grow with it so lines print as thick as they look
+    /// on screen.
+    pub dpi: f32,
+    /// Edge length of the square tiles rendered at once, in pixels.
+    pub tile_size: u32,
+    /// Anti-aliasing samples per pixel along each axis.
+    pub samples: u32,
+    /// Space kept around the drawing, in mm.
+    pub margin: f32,
+    pub background: Hsv,
+}
+
+impl PosterSettings {
+    pub fn default() -> Self {
+        PosterSettings {
+            width: 8000,
+            height: 8000,
+            dpi: 300.0,
+            tile_size: 512,
+            samples: 4,
+            margin: 10.0,
+            background: Hsv::new(0.0, 0.0, 0.0),
+        }
+    }
+    /// Printed size in mm.
+    pub fn size_mm(&self) -> Vec2 {
+        vec2(self.width as f32, self.height as f32) / self.dpi * MM_PER_INCH
+    }
+    /// Bytes of the pixel buffer held while rendering: one band of tiles across the full
+    /// width, since PNG rows have to be written top to bottom.
+    pub fn band_bytes(&self) -> usize {
+        self.width.max(1) as usize * self.tile_size.clamp(1, self.height.max(1)) as usize * 4
+    }
+}
+
+/// Renders the whole of `geometry` to a PNG at `path`, streaming rows to the encoder.
+/// Besides the drawing's own mesh, which is no larger than the one the window draws, only
+/// `PosterSettings::band_bytes` of pixels and the triangles crossing one band are held at
+/// once, however large the image is. `progress` is called with the rows written so far.
+pub fn render_poster(
+    geometry: &Geometry,
+    stroke: &StrokeStyle,
+    settings: &PosterSettings,
+    path: impl AsRef<Path>,
+    mut progress: impl FnMut(usize),
+) -> Result<String, ExportError> {
+    let path = path.as_ref();
+    let (width, height) = (settings.width.max(1), settings.height.max(1));
+    let dpi = settings.dpi.max(1.0);
+    let tile = settings.tile_size.max(1);
+
+    let factor = dpi / SCREEN_DPI;
+    let mut geometry = geometry.clone();
+    geometry.scale_widths(factor);
+    let margin = settings.margin / MM_PER_INCH * dpi;
+    geometry.map_points(frame_transform(geometry.bounds(), width, height, margin));
+    let mesh = Mesh::from_geometry(&geometry, &stroke.scaled(factor));
+    let raster = Raster::new(width, height, Rect::from_w_h(width as f32, height as f32))
+        .with_samples(settings.samples)
+        .with_background(settings.background);
+
+    let encode_error = |e: png::EncodingError| ExportError::Encode(e.to_string());
+    let file = File::create(path).map_err(|e| ExportError::Io(e.to_string()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let pixels_per_meter = (dpi / MM_PER_INCH * 1000.0).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+    let mut stream = encoder
+        .write_header()
+        .and_then(|writer| writer.into_stream_writer())
+        .map_err(encode_error)?;
+
+    let row_bytes = width as usize * 4;
+    for top in (0..height).step_by(tile as usize) {
+        let rows = top..(top + tile).min(height);
+        let band_mesh = mesh.within(raster.region(0..width, rows.clone()));
+        let mut band = vec![0; row_bytes * rows.len()];
+        for left in (0..width).step_by(tile as usize) {
+            let columns = left..(left + tile).min(width);
+            let tile_mesh = band_mesh.within(raster.region(columns.clone(), rows.clone()));
+            let pixels = raster.render_tile(&tile_mesh, columns.clone(), rows.clone());
+            let tile_bytes = columns.len() * 4;
+            for (row, tile_row) in pixels.chunks_exact(tile_bytes).enumerate() {
+                let start = row * row_bytes + left as usize * 4;
+                band[start..start + tile_bytes].copy_from_slice(tile_row);
+            }
+        }
+        stream
+            .write_all(&band)
+            .map_err(|e| ExportError::Io(e.to_string()))?;
+        progress(rows.end as usize);
+    }
+    stream.finish().map_err(encode_error)?;
+
+    let size = settings.size_mm();
+    Ok(format!(
+        "wrote {}: {}x{} px, {:.0}x{:.0} mm at {} dpi",
+        path.display(),
+        width,
+        height,
+        size.x,
+        size.y,
+        dpi
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentMeta;
+
+    #[test]
+    fn band_bytes_span_the_full_width() {
+        let settings = PosterSettings {
+            width: 1000,
+            height: 300,
+            tile_size: 512,
+            ..PosterSettings::default()
+        };
+        assert_eq!(settings.band_bytes(), 1000 * 300 * 4);
+        let settings = PosterSettings {
+            height: 2000,
+            ..settings
+        };
+        assert_eq!(settings.band_bytes(), 1000 * 512 * 4);
+    }
+
+    #[test]
+    fn renders_every_row_and_reports_progress() {
+        let meta = SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index: 0,
+            arc_length: 0.0,
+            branch_id: 0,
+        };
+        let mut geometry = Geometry::new();
+        let color = Hsv::new(0.0, 0.0, 1.0);
+        geometry.line(vec2(0.0, 0.0), vec2(10.0, 10.0), color, 1.0, meta);
+        let settings = PosterSettings {
+            width: 120,
+            height: 90,
+            tile_size: 32,
+            samples: 1,
+            ..PosterSettings::default()
+        };
+        let path = std::env::temp_dir().join("l-systems-poster.png");
+        let mut rows = Vec::new();
+        let result = render_poster(
+            &geometry,
+            &StrokeStyle::default(),
+            &settings,
+            &path,
+            |done| rows.push(done),
+        );
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(rows, vec![32, 64, 90]);
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let info = decoder.read_info().unwrap().info().clone();
+        assert_eq!((info.width, info.height), (120, 90));
+        std::fs::remove_file(path).unwrap();
+    }
+}
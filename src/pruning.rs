@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use nannou::prelude::*;
+
+/// Optional crowding rules applied while the turtle interprets a system. A segment that
+/// breaks one is dropped together with the rest of its branch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pruning {
+    /// Prune segments crossing or overlapping anything drawn before them.
+    pub intersections: bool,
+    /// Prune segments ending in a grid cell that already holds this many segment ends.
+    /// 0 disables the density check.
+    pub max_density: usize,
+    pub cell_size: f32,
+}
+
+impl Pruning {
+    pub fn new(intersections: bool, max_density: usize, cell_size: f32) -> Self {
+        Pruning {
+            intersections,
+            max_density,
+            cell_size,
+        }
+    }
+    pub fn default() -> Self {
+        Pruning::new(false, 0, 10.0)
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.intersections || self.max_density > 0
+    }
+}
+
+/// Segments drawn so far, bucketed by grid cell so checks only look at nearby segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionGrid {
+    cell_size: f32,
+    segments: HashMap<(i32, i32), Vec<(Vec2, Vec2)>>,
+    ends: HashMap<(i32, i32), usize>,
+}
+
+impl CollisionGrid {
+    pub fn new(cell_size: f32) -> Self {
+        CollisionGrid {
+            cell_size: cell_size.max(f32::EPSILON),
+            segments: HashMap::new(),
+            ends: HashMap::new(),
+        }
+    }
+    fn cell(&self, p: Vec2) -> (i32, i32) {
+        (
+            (p.x / self.cell_size).floor() as i32,
+            (p.y / self.cell_size).floor() as i32,
+        )
+    }
+    fn cells(&self, start: Vec2, end: Vec2) -> Vec<(i32, i32)> {
+        let (min, max) = (self.cell(start.min(end)), self.cell(start.max(end)));
+        (min.0..=max.0)
+            .flat_map(|x| (min.1..=max.1).map(move |y| (x, y)))
+            .collect()
+    }
+    pub fn insert(&mut self, start: Vec2, end: Vec2) {
+        for cell in self.cells(start, end) {
+            self.segments.entry(cell).or_default().push((start, end));
+        }
+        *self.ends.entry(self.cell(end)).or_default() += 1;
+    }
+    /// Whether `start..end` crosses or overlaps a stored segment. Touching at a shared
+    /// endpoint, as consecutive and sibling segments do, is not a collision.
+    pub fn intersects(&self, start: Vec2, end: Vec2) -> bool {
+        self.cells(start, end).into_iter().any(|cell| {
+            self.segments.get(&cell).map_or(false, |segments| {
+                segments
+                    .iter()
+                    .any(|&(a, b)| segments_intersect(start, end, a, b))
+            })
+        })
+    }
+    /// Number of stored segments ending in the cell containing `p`.
+    pub fn density(&self, p: Vec2) -> usize {
+        self.ends.get(&self.cell(p)).copied().unwrap_or(0)
+    }
+}
+
+/// Whether segments `a0..a1` and `b0..b1` share any point other than a common endpoint.
+pub fn segments_intersect(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> bool {
+    const EPSILON: f32 = 1e-4;
+    let (da, db) = (a1 - a0, b1 - b0);
+    let offset = b0 - a0;
+    let denominator = da.perp_dot(db);
+    let scale = da.length() * db.length();
+    if scale == 0.0 {
+        return false;
+    }
+
+    if denominator.abs() > EPSILON * scale {
+        // t along a, u along b
+        let t = offset.perp_dot(db) / denominator;
+        let u = offset.perp_dot(da) / denominator;
+        let inside = |s: f32| (-EPSILON..=1.0 + EPSILON).contains(&s);
+        let at_end = |s: f32| s.abs() <= EPSILON || (s - 1.0).abs() <= EPSILON;
+        return inside(t) && inside(u) && !(at_end(t) && at_end(u));
+    }
+
+    // parallel segments only collide when collinear and overlapping by more than a point
+    if offset.perp_dot(da).abs() > EPSILON * scale {
+        return false;
+    }
+    let length_squared = da.length_squared();
+    let s0 = offset.dot(da) / length_squared;
+    let s1 = (b1 - a0).dot(da) / length_squared;
+    let overlap = s0.max(s1).min(1.0) - s0.min(s1).max(0.0);
+    overlap > EPSILON
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossing_segments_intersect() {
+        let crossing = segments_intersect(
+            vec2(0.0, 0.0),
+            vec2(2.0, 2.0),
+            vec2(0.0, 2.0),
+            vec2(2.0, 0.0),
+        );
+        assert!(crossing);
+        let apart = segments_intersect(
+            vec2(0.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(3.0, 0.0),
+            vec2(2.0, 1.0),
+        );
+        assert!(!apart);
+    }
+
+    #[test]
+    fn touching_endpoints_do_not_intersect() {
+        // consecutive segments
+        let a = (vec2(0.0, 0.0), vec2(1.0, 1.0));
+        assert!(!segments_intersect(
+            a.0,
+            a.1,
+            vec2(1.0, 1.0),
+            vec2(2.0, 0.0)
+        ));
+        // siblings sharing a branch point
+        assert!(!segments_intersect(
+            a.0,
+            a.1,
+            vec2(0.0, 0.0),
+            vec2(-1.0, 1.0)
+        ));
+        // an end landing in the middle of another segment is a collision
+        assert!(segments_intersect(a.0, a.1, vec2(0.5, 0.5), vec2(1.0, 0.0)));
+    }
+
+    #[test]
+    fn collinear_overlap_intersects() {
+        let (a0, a1) = (vec2(0.0, 0.0), vec2(2.0, 0.0));
+        assert!(segments_intersect(a0, a1, vec2(1.0, 0.0), vec2(3.0, 0.0)));
+        // retracing a segment backwards
+        assert!(segments_intersect(a0, a1, a1, a0));
+        // contained
+        assert!(segments_intersect(a0, a1, vec2(0.5, 0.0), vec2(1.5, 0.0)));
+        // collinear but only sharing an endpoint
+        assert!(!segments_intersect(a0, a1, vec2(2.0, 0.0), vec2(4.0, 0.0)));
+        // collinear with a gap
+        assert!(!segments_intersect(a0, a1, vec2(3.0, 0.0), vec2(4.0, 0.0)));
+    }
+
+    #[test]
+    fn parallel_segments_do_not_intersect() {
+        let (a0, a1) = (vec2(0.0, 0.0), vec2(2.0, 2.0));
+        assert!(!segments_intersect(a0, a1, vec2(1.0, 0.0), vec2(3.0, 2.0)));
+        assert!(!segments_intersect(a0, a1, vec2(0.0, 0.1), vec2(2.0, 2.1)));
+    }
+
+    #[test]
+    fn degenerate_segments_do_not_intersect() {
+        let p = vec2(1.0, 1.0);
+        assert!(!segments_intersect(vec2(0.0, 0.0), vec2(2.0, 2.0), p, p));
+    }
+
+    #[test]
+    fn grid_query_spans_cells() {
+        let mut grid = CollisionGrid::new(10.0);
+        // crosses cells (0, 0) to (9, 0)
+        grid.insert(vec2(1.0, 5.0), vec2(95.0, 5.0));
+        // a short segment far along the long one, sharing only its last cell
+        assert!(grid.intersects(vec2(90.0, 0.0), vec2(90.0, 9.0)));
+        // a query spanning several cells vertically
+        assert!(grid.intersects(vec2(50.0, -25.0), vec2(50.0, 25.0)));
+        assert!(!grid.intersects(vec2(50.0, 6.0), vec2(50.0, 35.0)));
+        // beyond the stored segment
+        assert!(!grid.intersects(vec2(98.0, 0.0), vec2(98.0, 9.0)));
+        // negative coordinates land in their own cells
+        grid.insert(vec2(-15.0, -15.0), vec2(-5.0, -5.0));
+        assert!(grid.intersects(vec2(-15.0, -5.0), vec2(-5.0, -15.0)));
+    }
+
+    #[test]
+    fn density_counts_segment_ends() {
+        let mut grid = CollisionGrid::new(10.0);
+        grid.insert(vec2(0.0, 0.0), vec2(15.0, 15.0));
+        grid.insert(vec2(0.0, 0.0), vec2(12.0, 18.0));
+        assert_eq!(grid.density(vec2(11.0, 11.0)), 2);
+        assert_eq!(grid.density(vec2(1.0, 1.0)), 0);
+    }
+}
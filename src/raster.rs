@@ -0,0 +1,299 @@
+use std::{fs::File, io::BufWriter, ops::Range, path::Path};
+
+use nannou::prelude::*;
+
+use crate::{export::ExportError, mesh::Mesh};
+
+/// Rows rendered at once, which bounds the memory used by the supersampling buffer.
+const BAND_HEIGHT: u32 = 64;
+
+/// Software renderer for tessellated geometry, so images can be produced without a
+/// window or GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    /// Samples per pixel along each axis. 1 disables anti-aliasing.
+    pub samples: u32,
+    /// Region of the drawing shown in the image, scaled uniformly and centered so all of
+    /// it is visible.
+    pub view: Rect,
+    pub background: Hsv,
+}
+
+/// 8-bit sRGB pixels, four bytes per pixel, rows top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32, view: Rect) -> Self {
+        Raster {
+            width,
+            height,
+            samples: 4,
+            view,
+            background: Hsv::new(0.0, 0.0, 0.0),
+        }
+    }
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+    pub fn with_background(mut self, background: Hsv) -> Self {
+        self.background = background;
+        self
+    }
+    pub fn render(&self, mesh: &Mesh) -> RgbaImage {
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+        let mut top = 0;
+        while top < self.height {
+            let bottom = (top + BAND_HEIGHT).min(self.height);
+            pixels.extend(self.render_rows(mesh, top..bottom));
+            top = bottom;
+        }
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+    /// Renders the pixel rows `rows` of the image, so large images can be produced and
+    /// written a band at a time.
+    pub fn render_rows(&self, mesh: &Mesh, rows: Range<u32>) -> Vec<u8> {
+        self.render_tile(mesh, 0..self.width, rows)
+    }
+    /// Renders the pixels in `columns` and `rows` of the image, four bytes per pixel and
+    /// rows top to bottom.
+    pub fn render_tile(&self, mesh: &Mesh, columns: Range<u32>, rows: Range<u32>) -> Vec<u8> {
+        let samples = self.samples.max(1) as usize;
+        let width = columns.len() * samples;
+        let height = rows.len() * samples;
+        let background = linear(Srgb::from(self.background));
+        let mut buffer = vec![background; width * height];
+
+        // drawing space to sample space of this tile, y down
+        let view = self.visible();
+        let scale = self.scale() * samples as f32;
+        let to_samples = |p: Vec2| {
+            vec2(
+                (p.x - view.left()) * scale - (columns.start as usize * samples) as f32,
+                (view.top() - p.y) * scale - (rows.start as usize * samples) as f32,
+            )
+        };
+        let positions: Vec<Vec2> = mesh.vertices.iter().map(|&p| to_samples(p)).collect();
+        let colors: Vec<[f32; 4]> = mesh
+            .colors
+            .iter()
+            .map(|c| {
+                let [r, g, b] = linear(Srgb::from(c.color));
+                [r, g, b, c.alpha]
+            })
+            .collect();
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+            let (pa, pb, pc) = (positions[a], positions[b], positions[c]);
+            let area = (pb - pa).perp_dot(pc - pa);
+            if area == 0.0 {
+                continue;
+            }
+            let min = pa.min(pb).min(pc);
+            let max = pa.max(pb).max(pc);
+            let x0 = min.x.floor().max(0.0) as usize;
+            let y0 = min.y.floor().max(0.0) as usize;
+            let x1 = (max.x.ceil().max(0.0) as usize).min(width);
+            let y1 = (max.y.ceil().max(0.0) as usize).min(height);
+
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                    // barycentric weights, all the same sign as the area inside
+                    let wa = (pc - pb).perp_dot(p - pb) / area;
+                    let wb = (pa - pc).perp_dot(p - pc) / area;
+                    let wc = 1.0 - wa - wb;
+                    if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                        continue;
+                    }
+                    let color: [f32; 4] = std::array::from_fn(|i| {
+                        colors[a][i] * wa + colors[b][i] * wb + colors[c][i] * wc
+                    });
+                    let alpha = color[3].clamp(0.0, 1.0);
+                    let target = &mut buffer[y * width + x];
+                    for i in 0..3 {
+                        target[i] = color[i] * alpha + target[i] * (1.0 - alpha);
+                    }
+                }
+            }
+        }
+
+        // box filter every block of samples down to one pixel
+        let mut pixels = Vec::with_capacity(columns.len() * rows.len() * 4);
+        for row in 0..rows.len() {
+            for column in 0..columns.len() {
+                let mut sum = [0.0; 3];
+                for sy in 0..samples {
+                    for sx in 0..samples {
+                        let sample = buffer[(row * samples + sy) * width + column * samples + sx];
+                        (0..3).for_each(|i| sum[i] += sample[i]);
+                    }
+                }
+                let count = (samples * samples) as f32;
+                pixels.extend(sum.map(|c| encode(c / count)));
+                pixels.push(255);
+            }
+        }
+        pixels
+    }
+    /// Pixels per drawing unit, the same along both axes so the drawing keeps its
+    /// proportions whatever the aspect ratio of the image.
+    pub fn scale(&self) -> f32 {
+        let scale = match (self.view.w() > 0.0, self.view.h() > 0.0) {
+            (true, true) => {
+                (self.width as f32 / self.view.w()).min(self.height as f32 / self.view.h())
+            }
+            (true, false) => self.width as f32 / self.view.w(),
+            (false, true) => self.height as f32 / self.view.h(),
+            (false, false) => 1.0,
+        };
+        scale.max(f32::EPSILON)
+    }
+    /// The part of the drawing covering the whole image: `view` centered, with room added
+    /// on the sides or above and below when the aspect ratios differ.
+    pub fn visible(&self) -> Rect {
+        let size = vec2(self.width as f32, self.height as f32) / self.scale();
+        Rect::from_xy_wh(self.view.xy(), size)
+    }
+    /// The part of the drawing covered by the pixels in `columns` and `rows`.
+    pub fn region(&self, columns: Range<u32>, rows: Range<u32>) -> Rect {
+        let view = self.visible();
+        let pixel = 1.0 / self.scale();
+        Rect::from_corners(
+            vec2(
+                view.left() + columns.start as f32 * pixel,
+                view.top() - rows.end as f32 * pixel,
+            ),
+            vec2(
+                view.left() + columns.end as f32 * pixel,
+                view.top() - rows.start as f32 * pixel,
+            ),
+        )
+    }
+}
+
+impl RgbaImage {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), ExportError> {
+        let file = File::create(path).map_err(|e| ExportError::Io(e.to_string()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| ExportError::Encode(e.to_string()))
+    }
+}
+
+/// sRGB to linear light, where blending and filtering happen like they do on the GPU.
+fn linear(color: Srgb) -> [f32; 3] {
+    [color.red, color.green, color.blue].map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+fn encode(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disc(radius: f32) -> Mesh {
+        let mut mesh = Mesh::new();
+        mesh.disc(vec2(0.0, 0.0), radius, Hsva::new(0.0, 0.0, 1.0, 1.0));
+        mesh
+    }
+
+    /// Lit pixels along the middle row and the middle column.
+    fn extents(image: &RgbaImage) -> (usize, usize) {
+        let lit = |x: u32, y: u32| image.pixels[((y * image.width + x) * 4) as usize] > 127;
+        let across = (0..image.width)
+            .filter(|&x| lit(x, image.height / 2))
+            .count();
+        let down = (0..image.height)
+            .filter(|&y| lit(image.width / 2, y))
+            .count();
+        (across, down)
+    }
+
+    #[test]
+    fn wide_image_keeps_proportions() {
+        let raster = Raster::new(200, 100, Rect::from_w_h(4.0, 4.0)).with_samples(1);
+        assert_eq!(raster.scale(), 25.0);
+        assert_eq!(raster.visible(), Rect::from_w_h(8.0, 4.0));
+        let (across, down) = extents(&raster.render(&disc(1.0)));
+        // a circle 50 pixels across, not stretched into an ellipse
+        assert!(across.abs_diff(50) <= 1, "{}", across);
+        assert!(down.abs_diff(50) <= 1, "{}", down);
+    }
+
+    #[test]
+    fn tall_image_keeps_proportions() {
+        let raster = Raster::new(60, 120, Rect::from_x_y_w_h(5.0, 5.0, 6.0, 3.0)).with_samples(1);
+        assert_eq!(raster.scale(), 10.0);
+        assert_eq!(raster.visible(), Rect::from_x_y_w_h(5.0, 5.0, 6.0, 12.0));
+        let mut mesh = Mesh::new();
+        mesh.disc(vec2(5.0, 5.0), 2.0, Hsva::new(0.0, 0.0, 1.0, 1.0));
+        let (across, down) = extents(&raster.render(&mesh));
+        assert!(across.abs_diff(40) <= 1, "{}", across);
+        assert!(down.abs_diff(40) <= 1, "{}", down);
+    }
+
+    #[test]
+    fn regions_tile_the_visible_view() {
+        let raster = Raster::new(200, 100, Rect::from_w_h(4.0, 4.0));
+        assert_eq!(raster.region(0..200, 0..100), raster.visible());
+        assert_eq!(
+            raster.region(0..100, 0..50),
+            Rect::from_x_y_w_h(-2.0, 1.0, 4.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn tiles_match_the_whole_image() {
+        let raster = Raster::new(40, 30, Rect::from_w_h(4.0, 4.0)).with_samples(2);
+        let mesh = disc(1.5);
+        let image = raster.render(&mesh);
+        let tile = raster.render_tile(&mesh, 10..25, 5..20);
+        for (row, y) in (5..20).enumerate() {
+            let start = (y * 40 + 10) * 4;
+            assert_eq!(
+                tile[row * 15 * 4..(row + 1) * 15 * 4],
+                image.pixels[start..start + 60]
+            );
+        }
+    }
+
+    #[test]
+    fn background_fills_empty_images() {
+        let raster =
+            Raster::new(3, 2, Rect::from_w_h(1.0, 1.0)).with_background(Hsv::new(0.0, 1.0, 1.0));
+        let image = raster.render(&Mesh::new());
+        assert_eq!(image.pixels, [255, 0, 0, 255].repeat(6));
+    }
+}
@@ -0,0 +1,18 @@
+use nannou::prelude::*;
+
+use crate::mesh::Mesh;
+
+/// Draws a tessellated mesh with nannou in one call. Everything on screen goes through
+/// `Mesh`, so the window and the exports share one implementation of caps, joins and glow.
+pub fn draw_mesh(draw: &Draw, mesh: &Mesh) {
+    if mesh.is_empty() {
+        return;
+    }
+    draw.mesh().indexed_colored(
+        mesh.vertices
+            .iter()
+            .zip(&mesh.colors)
+            .map(|(p, &color)| (p.extend(0.0), color)),
+        mesh.indices.iter().copied(),
+    );
+}
@@ -1,13 +1,20 @@
 use nannou::prelude::*;
 
-use crate::{DrawableLSystem, LSystemRules};
+use crate::{
+    color_palette::Palette, geometry::Geometry, jitter::Jitter, stroke::StrokeStyle,
+    DrawableLSystem, LSystemDrawingParamaters, LSystemRules, Tropism,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SierpinskiTriangleLSystem {
     pub line_length: f32,
     pub start_pos: Vec2,
     pub start_angle: f32,
     pub draw_color: Hsv,
+    pub palette: Palette,
+    pub tropism: Tropism,
+    pub jitter: Jitter,
+    pub stroke: StrokeStyle,
 }
 
 impl SierpinskiTriangleLSystem {
@@ -17,44 +24,59 @@ impl SierpinskiTriangleLSystem {
             start_pos,
             start_angle,
             draw_color,
+            palette: Palette::default(),
+            tropism: Tropism::default(),
+            jitter: Jitter::default(),
+            stroke: StrokeStyle::default(),
         }
     }
 }
 
 impl DrawableLSystem for SierpinskiTriangleLSystem {
-    fn draw(&self, draw: &Draw, _win: &Rect<f32>, levels: &usize) {
-        let evaluated_lsystem = sierpinski_triangle_rules_object()
-            .eval(levels)
-            .expect("sierpinski triangle lsystem evaluation failed");
+    fn geometry(&self, levels: &usize) -> Geometry {
+        let mut turtle = LSystemDrawingParamaters::new(self.start_pos, self.start_angle)
+            .with_tropism(self.tropism)
+            .with_jitter(self.jitter);
+        let mut geometry = Geometry::new();
 
-        let system_iter = evaluated_lsystem.chars();
-        let mut pos = self.start_pos;
-        let mut angle = self.start_angle;
-
-        for c in system_iter {
+        for (index, (c, generation)) in sierpinski_triangle_rules_object()
+            .eval_generations(levels)
+            .into_iter()
+            .enumerate()
+        {
+            turtle.set_symbol(index, generation);
             match c {
                 'F' | 'G' => {
-                    let new_pos = pos + vec2(0.0, self.line_length).rotate(angle);
-                    draw.line()
-                        .start(pos)
-                        .end(new_pos)
-                        .color(self.draw_color)
-                        .stroke_weight(2.0);
-                    pos = new_pos;
+                    let (start, end) = turtle.forward(self.line_length);
+                    geometry.line(
+                        start,
+                        end,
+                        self.palette.color(&turtle.state, self.draw_color),
+                        2.0,
+                        turtle.segment_meta(),
+                    );
                 }
                 '+' => {
-                    angle += 2.0 * PI / 3.0;
+                    turtle.turn(2.0 * PI / 3.0);
                 }
                 '-' => {
-                    angle -= 2.0 * PI / 3.0;
+                    turtle.turn(-2.0 * PI / 3.0);
+                }
+                '\'' => {
+                    turtle.next_color();
                 }
                 _ => (),
             }
         }
+
+        geometry
     }
     fn get_rules(&self) -> LSystemRules {
         sierpinski_triangle_rules_object()
     }
+    fn stroke_style(&self) -> &StrokeStyle {
+        &self.stroke
+    }
 }
 
 pub fn sierpinski_triangle_rules_object() -> LSystemRules {
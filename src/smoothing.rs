@@ -0,0 +1,291 @@
+use nannou::prelude::*;
+
+use crate::geometry::Geometry;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmoothingMode {
+    Off,
+    /// Cardinal spline through every turtle vertex.
+    CatmullRom,
+    /// Quadratic Bézier corners between segment midpoints.
+    Bezier,
+}
+
+impl SmoothingMode {
+    pub const ALL: [SmoothingMode; 3] = [
+        SmoothingMode::Off,
+        SmoothingMode::CatmullRom,
+        SmoothingMode::Bezier,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SmoothingMode::Off => "Off",
+            SmoothingMode::CatmullRom => "Catmull-Rom",
+            SmoothingMode::Bezier => "Bezier",
+        }
+    }
+}
+
+/// Replaces the straight segments of every polyline with a spline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Smoothing {
+    pub mode: SmoothingMode,
+    /// 0 is fully rounded, 1 keeps the original corners.
+    pub tension: f32,
+    /// Points generated per original segment.
+    pub subdivisions: usize,
+}
+
+impl Smoothing {
+    pub fn new(mode: SmoothingMode, tension: f32, subdivisions: usize) -> Self {
+        Smoothing {
+            mode,
+            tension,
+            subdivisions,
+        }
+    }
+    pub fn default() -> Self {
+        Smoothing::new(SmoothingMode::Off, 0.0, 8)
+    }
+    pub fn apply(&self, geometry: &mut Geometry) {
+        if self.mode == SmoothingMode::Off {
+            return;
+        }
+        for polyline in &mut geometry.polylines {
+            let original = polyline.segments.len();
+            polyline.points = self.smooth(&polyline.points);
+
+            // each smoothed segment keeps the metadata and color of the turtle segment it
+            // replaces: both modes emit the same number of points per turtle segment, so
+            // smoothed segment `i` of `count` falls in turtle segment `i * original / count`,
+            // and the point ending it takes the color of that turtle segment's end
+            let count = polyline.points.len().saturating_sub(1);
+            let source = |i: usize| i * original / count;
+            polyline.colors = (0..=count)
+                .map(|i| match i {
+                    0 => polyline.colors[0],
+                    i => polyline.colors[source(i - 1) + 1],
+                })
+                .collect();
+            polyline.segments = (0..count).map(|i| polyline.segments[source(i)]).collect();
+        }
+    }
+    pub fn smooth(&self, points: &[Vec2]) -> Vec<Vec2> {
+        if points.len() < 3 {
+            return points.to_vec();
+        }
+        match self.mode {
+            SmoothingMode::Off => points.to_vec(),
+            SmoothingMode::CatmullRom => self.catmull_rom(points),
+            SmoothingMode::Bezier => self.bezier(points),
+        }
+    }
+
+    fn catmull_rom(&self, points: &[Vec2]) -> Vec<Vec2> {
+        let closed = is_closed(points);
+        let n = points.len();
+        // neighbours past the ends wrap around closed loops and repeat the end otherwise
+        let at = |i: isize| -> Vec2 {
+            if closed {
+                points[i.rem_euclid(n as isize - 1) as usize]
+            } else {
+                points[i.clamp(0, n as isize - 1) as usize]
+            }
+        };
+        let scale = (1.0 - self.tension) / 2.0;
+        let steps = self.subdivisions.max(1);
+
+        let mut smoothed = vec![points[0]];
+        for i in 0..n as isize - 1 {
+            let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+            let m1 = (p2 - p0) * scale;
+            let m2 = (p3 - p1) * scale;
+            for step in 1..=steps {
+                let t = step as f32 / steps as f32;
+                let (t2, t3) = (t * t, t * t * t);
+                smoothed.push(
+                    p1 * (2.0 * t3 - 3.0 * t2 + 1.0)
+                        + m1 * (t3 - 2.0 * t2 + t)
+                        + p2 * (-2.0 * t3 + 3.0 * t2)
+                        + m2 * (t3 - t2),
+                );
+            }
+        }
+        smoothed
+    }
+
+    fn bezier(&self, points: &[Vec2]) -> Vec<Vec2> {
+        let closed = is_closed(points);
+        let n = points.len();
+        let steps = self.subdivisions.max(1);
+        let rounding = 1.0 - self.tension.clamp(0.0, 1.0);
+
+        let corner = |prev: Vec2, vertex: Vec2, next: Vec2, out: &mut Vec<Vec2>| {
+            let start = vertex.lerp((prev + vertex) / 2.0, rounding);
+            let end = vertex.lerp((vertex + next) / 2.0, rounding);
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                out.push(start.lerp(vertex, t).lerp(vertex.lerp(end, t), t));
+            }
+        };
+
+        let mut smoothed = Vec::new();
+        if closed {
+            corner(points[n - 2], points[0], points[1], &mut smoothed);
+        } else {
+            smoothed.push(points[0]);
+        }
+        for i in 1..n - 1 {
+            corner(points[i - 1], points[i], points[i + 1], &mut smoothed);
+        }
+        if closed {
+            smoothed.push(smoothed[0]);
+        } else {
+            smoothed.push(points[n - 1]);
+        }
+        smoothed
+    }
+}
+
+fn is_closed(points: &[Vec2]) -> bool {
+    points.len() > 3 && points.first() == points.last()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentMeta;
+
+    fn meta(index: usize) -> SegmentMeta {
+        SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index,
+            arc_length: 0.0,
+            branch_id: 0,
+        }
+    }
+
+    /// One polyline through `points`, each segment in its own hue and indexed by position.
+    fn polyline(points: &[Vec2]) -> Geometry {
+        let mut geometry = Geometry::new();
+        for (i, pair) in points.windows(2).enumerate() {
+            let color = Hsv::new(i as f32 * 30.0, 1.0, 1.0);
+            geometry.line(pair[0], pair[1], color, 1.0, meta(i));
+        }
+        geometry
+    }
+
+    fn zigzag() -> Vec<Vec2> {
+        vec![
+            vec2(0.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(2.0, 0.0),
+            vec2(3.0, 1.0),
+        ]
+    }
+
+    fn square() -> Vec<Vec2> {
+        vec![
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(0.0, 1.0),
+            vec2(0.0, 0.0),
+        ]
+    }
+
+    const MODES: [SmoothingMode; 2] = [SmoothingMode::CatmullRom, SmoothingMode::Bezier];
+
+    #[test]
+    fn open_polylines_keep_their_endpoints() {
+        for mode in MODES {
+            let smoothed = Smoothing::new(mode, 0.0, 4).smooth(&zigzag());
+            assert_eq!(smoothed.first(), Some(&vec2(0.0, 0.0)), "{:?}", mode);
+            assert_eq!(smoothed.last(), Some(&vec2(3.0, 1.0)), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn closed_polylines_stay_closed() {
+        for mode in MODES {
+            let smoothed = Smoothing::new(mode, 0.0, 4).smooth(&square());
+            assert_eq!(smoothed.first(), smoothed.last(), "{:?}", mode);
+        }
+        let smoothed = Smoothing::new(SmoothingMode::CatmullRom, 0.0, 4).smooth(&square());
+        assert_eq!(smoothed[0], vec2(0.0, 0.0));
+    }
+
+    #[test]
+    fn full_tension_keeps_the_corners() {
+        for mode in MODES {
+            let smoothed = Smoothing::new(mode, 1.0, 4).smooth(&zigzag());
+            for corner in zigzag() {
+                assert!(
+                    smoothed.iter().any(|p| p.distance(corner) < 1e-5),
+                    "{:?} lost {:?}",
+                    mode,
+                    corner
+                );
+            }
+            // and everything in between stays on the original segments
+            for p in &smoothed {
+                let on_segment = zigzag().windows(2).any(|pair| {
+                    let along =
+                        (*p - pair[0]).dot(pair[1] - pair[0]) / pair[0].distance_squared(pair[1]);
+                    (0.0..=1.0).contains(&along) && p.distance(pair[0].lerp(pair[1], along)) < 1e-5
+                });
+                assert!(on_segment, "{:?} strayed to {:?}", mode, p);
+            }
+        }
+    }
+
+    #[test]
+    fn colors_and_metadata_follow_the_points() {
+        for mode in MODES {
+            let mut geometry = polyline(&zigzag());
+            let original = geometry.polylines[0].clone();
+            Smoothing::new(mode, 0.0, 4).apply(&mut geometry);
+            let smoothed = &geometry.polylines[0];
+            assert_eq!(smoothed.colors.len(), smoothed.points.len(), "{:?}", mode);
+            assert_eq!(
+                smoothed.segments.len(),
+                smoothed.points.len() - 1,
+                "{:?}",
+                mode
+            );
+            assert_eq!(smoothed.colors[0], original.colors[0]);
+            assert_eq!(smoothed.colors.last(), original.colors.last());
+            assert_eq!(smoothed.segments[0], meta(0));
+            assert_eq!(smoothed.segments.last(), Some(&meta(2)));
+            // every turtle segment is represented, in order
+            let mut indices: Vec<usize> = smoothed.segments.iter().map(|m| m.index).collect();
+            assert!(
+                indices.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{:?}",
+                mode
+            );
+            indices.dedup();
+            assert_eq!(indices, vec![0, 1, 2], "{:?}", mode);
+        }
+        // Catmull-Rom emits exactly `subdivisions` points per segment
+        let mut geometry = polyline(&zigzag());
+        let original = geometry.polylines[0].clone();
+        Smoothing::new(SmoothingMode::CatmullRom, 0.0, 4).apply(&mut geometry);
+        let smoothed = &geometry.polylines[0];
+        for (i, color) in smoothed.colors.iter().enumerate().skip(1) {
+            assert_eq!(*color, original.colors[(i - 1) / 4 + 1], "point {}", i);
+        }
+    }
+
+    #[test]
+    fn short_polylines_pass_through() {
+        for mode in MODES {
+            let mut geometry = polyline(&[vec2(0.0, 0.0), vec2(1.0, 2.0)]);
+            let original = geometry.clone();
+            Smoothing::new(mode, 0.0, 4).apply(&mut geometry);
+            assert_eq!(geometry, original, "{:?}", mode);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use nannou::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+impl LineCap {
+    pub const ALL: [LineCap; 3] = [LineCap::Butt, LineCap::Round, LineCap::Square];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineCap::Butt => "Butt",
+            LineCap::Round => "Round",
+            LineCap::Square => "Square",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    pub const ALL: [LineJoin; 3] = [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineJoin::Miter => "Miter",
+            LineJoin::Round => "Round",
+            LineJoin::Bevel => "Bevel",
+        }
+    }
+}
+
+/// Wider, translucent copies of every stroke drawn underneath it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glow {
+    pub passes: usize,
+    /// Extra width of the outermost pass.
+    pub width: f32,
+    /// Combined opacity of all passes.
+    pub intensity: f32,
+}
+
+impl Glow {
+    pub fn new(passes: usize, width: f32, intensity: f32) -> Self {
+        Glow {
+            passes,
+            width,
+            intensity,
+        }
+    }
+    pub fn default() -> Self {
+        Glow::new(0, 12.0, 0.5)
+    }
+    /// Width added and opacity of each pass, outermost first.
+    pub fn layers(&self) -> Vec<(f32, f32)> {
+        let alpha = self.intensity / self.passes.max(1) as f32;
+        (1..=self.passes)
+            .rev()
+            .map(|pass| (self.width * pass as f32 / self.passes as f32, alpha))
+            .collect()
+    }
+}
+
+/// How polylines are stroked when drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Alternating dash and gap lengths. Empty draws solid lines, and an odd count is
+    /// repeated to make it even, as SVG and PDF do.
+    pub dashes: Vec<f32>,
+    /// Distance into the dash pattern the first segment starts at.
+    pub dash_offset: f32,
+    pub glow: Glow,
+}
+
+impl StrokeStyle {
+    pub fn new(cap: LineCap, join: LineJoin) -> Self {
+        StrokeStyle {
+            cap,
+            join,
+            dashes: Vec::new(),
+            dash_offset: 0.0,
+            glow: Glow::default(),
+        }
+    }
+    pub fn default() -> Self {
+        StrokeStyle::new(LineCap::Butt, LineJoin::Miter)
+    }
+    /// The style with every length multiplied by `factor`, for drawing at another
+    /// resolution.
+    pub fn scaled(&self, factor: f32) -> StrokeStyle {
+        StrokeStyle {
+            dashes: self.dashes.iter().map(|dash| dash * factor).collect(),
+            dash_offset: self.dash_offset * factor,
+            glow: Glow {
+                width: self.glow.width * factor,
+                ..self.glow
+            },
+            ..self.clone()
+        }
+    }
+    /// The dash pattern with dashes and gaps alternating, so `[5]` is 5 on and 5 off.
+    pub fn dash_pattern(&self) -> Vec<f32> {
+        match self.dashes.len() % 2 {
+            0 => self.dashes.clone(),
+            _ => self.dashes.repeat(2),
+        }
+    }
+    /// Splits a polyline into the runs drawn by the dash pattern. Dashes continue across
+    /// vertices, so corners inside a dash keep their join. Points cut inside a segment take
+    /// the color of the segment's end.
+    pub fn dash(&self, points: &[Vec2], colors: &[Hsv]) -> Vec<Vec<(Vec2, Hsv)>> {
+        let colored: Vec<(Vec2, Hsv)> =
+            points.iter().copied().zip(colors.iter().copied()).collect();
+        let pattern = self.dash_pattern();
+        let period: f32 = pattern.iter().sum();
+        if pattern.is_empty() || period <= 0.0 || colored.len() < 2 {
+            return vec![colored];
+        }
+
+        // find where in the pattern the offset lands
+        let mut dash = 0;
+        let mut remaining = pattern[0];
+        let mut skip = self.dash_offset.rem_euclid(period);
+        while skip >= remaining {
+            skip -= remaining;
+            dash = (dash + 1) % pattern.len();
+            remaining = pattern[dash];
+        }
+        remaining -= skip;
+
+        let mut runs = Vec::new();
+        let mut current = vec![colored[0]];
+        for segment in colored.windows(2) {
+            let (mut start, (end, color)) = (segment[0].0, segment[1]);
+            let mut length = start.distance(end);
+            while length > remaining {
+                let split = (start.lerp(end, remaining / length), color);
+                if dash % 2 == 0 {
+                    current.push(split);
+                    runs.push(std::mem::take(&mut current));
+                } else {
+                    current = vec![split];
+                }
+                length -= remaining;
+                start = split.0;
+                dash = (dash + 1) % pattern.len();
+                remaining = pattern[dash];
+            }
+            remaining -= length;
+            if dash % 2 == 0 {
+                current.push(segment[1]);
+            }
+        }
+        if dash % 2 == 0 && current.len() > 1 {
+            runs.push(current);
+        }
+        runs.retain(|run| run.len() > 1);
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dashed(dashes: Vec<f32>, dash_offset: f32) -> StrokeStyle {
+        StrokeStyle {
+            dashes,
+            dash_offset,
+            ..StrokeStyle::default()
+        }
+    }
+
+    /// Start and end x of each run dashing a straight line from 0 to `length`.
+    fn runs(style: &StrokeStyle, length: f32) -> Vec<(f32, f32)> {
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        style
+            .dash(&[vec2(0.0, 0.0), vec2(length, 0.0)], &[color, color])
+            .iter()
+            .map(|run| (run[0].0.x, run[run.len() - 1].0.x))
+            .collect()
+    }
+
+    #[test]
+    fn no_dashes_draw_solid() {
+        assert_eq!(runs(&StrokeStyle::default(), 10.0), [(0.0, 10.0)]);
+    }
+
+    #[test]
+    fn even_pattern_alternates() {
+        let style = dashed(vec![4.0, 2.0], 0.0);
+        assert_eq!(runs(&style, 14.0), [(0.0, 4.0), (6.0, 10.0), (12.0, 14.0)]);
+    }
+
+    #[test]
+    fn odd_pattern_repeats_like_svg() {
+        let style = dashed(vec![5.0], 0.0);
+        assert_eq!(style.dash_pattern(), [5.0, 5.0]);
+        assert_eq!(runs(&style, 20.0), [(0.0, 5.0), (10.0, 15.0)]);
+
+        let style = dashed(vec![3.0, 1.0, 2.0], 0.0);
+        assert_eq!(style.dash_pattern(), [3.0, 1.0, 2.0, 3.0, 1.0, 2.0]);
+        // on 3, off 1, on 2, off 3, on 1, off 2
+        assert_eq!(runs(&style, 12.0), [(0.0, 3.0), (4.0, 6.0), (9.0, 10.0)]);
+    }
+
+    #[test]
+    fn offset_shifts_the_pattern() {
+        let style = dashed(vec![4.0, 2.0], 5.0);
+        assert_eq!(runs(&style, 10.0), [(1.0, 5.0), (7.0, 10.0)]);
+    }
+
+    #[test]
+    fn dashes_continue_across_vertices() {
+        let color = Hsv::new(0.0, 1.0, 1.0);
+        let points = [vec2(0.0, 0.0), vec2(3.0, 0.0), vec2(3.0, 3.0)];
+        let runs = dashed(vec![4.0, 1.0], 0.0).dash(&points, &[color; 3]);
+        assert_eq!(runs.len(), 2);
+        // the first dash turns the corner
+        let first: Vec<Vec2> = runs[0].iter().map(|&(p, _)| p).collect();
+        assert_eq!(first, [vec2(0.0, 0.0), vec2(3.0, 0.0), vec2(3.0, 1.0)]);
+        assert_eq!(runs[1][0].0, vec2(3.0, 2.0));
+    }
+}
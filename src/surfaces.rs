@@ -0,0 +1,392 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use nannou::prelude::*;
+
+/// A predefined shape the turtle places with `~X`. Shapes live in turtle space: the
+/// attachment point is the origin and the turtle's heading points along +Y.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Surface {
+    pub polygons: Vec<Vec<Vec2>>,
+    pub color: Hsv,
+}
+
+impl Surface {
+    pub fn new(polygons: Vec<Vec<Vec2>>, color: Hsv) -> Self {
+        Surface { polygons, color }
+    }
+    /// A pointed leaf one unit long.
+    pub fn leaf() -> Self {
+        let steps = 12;
+        let side = |sign: f32| {
+            (0..=steps).map(move |i| {
+                let t = i as f32 / steps as f32;
+                vec2(sign * 0.25 * (PI * t).sin(), t)
+            })
+        };
+        let outline = side(1.0).chain(side(-1.0).rev().skip(1)).collect();
+
+        Surface::new(vec![outline], hsv(0.3, 0.8, 0.7))
+    }
+    /// A round bud one unit across.
+    pub fn bud() -> Self {
+        let outline = (0..16)
+            .map(|i| vec2(0.0, 0.5) + vec2(0.0, 0.5).rotate(i as f32 * TAU / 16.0))
+            .collect();
+
+        Surface::new(vec![outline], hsv(0.95, 0.6, 0.9))
+    }
+    /// A five petalled flower one unit across.
+    pub fn flower() -> Self {
+        let outline = (0..10)
+            .map(|i| {
+                let radius = if i % 2 == 0 { 0.5 } else { 0.2 };
+                vec2(0.0, 0.5) + vec2(0.0, radius).rotate(i as f32 * TAU / 10.0)
+            })
+            .collect();
+
+        Surface::new(vec![outline], hsv(0.15, 0.9, 1.0))
+    }
+    /// Reads the `<polygon>`, `<polyline>` and straight-line `<path>` elements of an SVG
+    /// document. The result is flipped to y-up and scaled to one unit tall with the middle
+    /// of its bottom edge at the origin.
+    pub fn from_svg(svg: &str, color: Hsv) -> Result<Self, SurfaceError> {
+        let mut polygons = Vec::new();
+        for (tag, attribute) in [
+            ("<polygon", "points"),
+            ("<polyline", "points"),
+            ("<path", "d"),
+        ] {
+            for element in svg.split(tag).skip(1) {
+                let element = &element[..element.find('>').unwrap_or(element.len())];
+                let Some(value) = attribute_value(element, attribute) else {
+                    continue;
+                };
+                if attribute == "d" {
+                    polygons.extend(parse_path(value)?);
+                } else {
+                    polygons.push(parse_points(value)?);
+                }
+            }
+        }
+        polygons.retain(|polygon| polygon.len() >= 3);
+        if polygons.is_empty() {
+            return Err(SurfaceError::NoShapes);
+        }
+
+        let all = polygons.iter().flatten().map(|p| vec2(p.x, -p.y));
+        let (min, max) = all.fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), p| (min.min(p), max.max(p)),
+        );
+        let size = if max.y > min.y {
+            max.y - min.y
+        } else {
+            max.x - min.x
+        };
+        let size = if size > 0.0 { size } else { 1.0 };
+        let base = vec2((min.x + max.x) / 2.0, min.y);
+
+        for polygon in &mut polygons {
+            for p in polygon.iter_mut() {
+                *p = (vec2(p.x, -p.y) - base) / size;
+            }
+        }
+        Ok(Surface::new(polygons, color))
+    }
+    pub fn from_svg_file(path: impl AsRef<Path>, color: Hsv) -> Result<Self, SurfaceError> {
+        let svg = fs::read_to_string(path).map_err(|e| SurfaceError::Io(e.to_string()))?;
+        Surface::from_svg(&svg, color)
+    }
+    /// The polygons moved to `pos`, turned to `angle` and scaled by `scale`.
+    pub fn place(&self, pos: Vec2, angle: f32, scale: f32) -> Vec<Vec<Vec2>> {
+        self.polygons
+            .iter()
+            .map(|polygon| {
+                polygon
+                    .iter()
+                    .map(|&p| pos + (p * scale).rotate(angle))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SurfaceError {
+    Io(String),
+    NoShapes,
+    UnsupportedCommand(char),
+    BadNumber(String),
+}
+
+impl fmt::Display for SurfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SurfaceError::Io(error) => write!(f, "could not read svg: {}", error),
+            SurfaceError::NoShapes => write!(f, "svg has no polygons or straight paths"),
+            SurfaceError::UnsupportedCommand(c) => {
+                write!(f, "svg path command '{}' is not supported", c)
+            }
+            SurfaceError::BadNumber(n) => write!(f, "could not parse svg number '{}'", n),
+        }
+    }
+}
+
+/// Shapes available to `~X`, keyed by the symbol following the `~`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceRegistry {
+    pub surfaces: BTreeMap<char, Surface>,
+}
+
+impl SurfaceRegistry {
+    pub fn new() -> Self {
+        SurfaceRegistry {
+            surfaces: BTreeMap::new(),
+        }
+    }
+    /// `L` leaf, `B` bud and `W` flower.
+    pub fn default() -> Self {
+        let mut registry = SurfaceRegistry::new();
+        registry.insert('L', Surface::leaf());
+        registry.insert('B', Surface::bud());
+        registry.insert('W', Surface::flower());
+        registry
+    }
+    pub fn insert(&mut self, name: char, surface: Surface) {
+        self.surfaces.insert(name, surface);
+    }
+    pub fn get(&self, name: char) -> Option<&Surface> {
+        self.surfaces.get(&name)
+    }
+}
+
+/// The quoted value of `attribute`. The name has to follow whitespace, any whitespace
+/// including newlines, so `d` does not match the end of `id`.
+fn attribute_value<'a>(element: &'a str, attribute: &str) -> Option<&'a str> {
+    element
+        .match_indices(attribute)
+        .filter(|&(start, _)| element[..start].ends_with(char::is_whitespace))
+        .find_map(|(start, _)| {
+            let rest = element[start + attribute.len()..].trim_start();
+            let rest = rest.strip_prefix('=')?.trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let rest = &rest[1..];
+            Some(&rest[..rest.find(quote)?])
+        })
+}
+
+enum PathToken {
+    Command(char),
+    Number(f32),
+}
+
+fn tokenize(value: &str) -> Result<Vec<PathToken>, SurfaceError> {
+    let mut tokens = Vec::new();
+    let mut number = String::new();
+    let flush = |number: &mut String, tokens: &mut Vec<PathToken>| {
+        if number.is_empty() {
+            return Ok(());
+        }
+        let parsed = number
+            .parse()
+            .map_err(|_| SurfaceError::BadNumber(number.clone()))?;
+        tokens.push(PathToken::Number(parsed));
+        number.clear();
+        Ok(())
+    };
+
+    for c in value.chars() {
+        match c {
+            '0'..='9' | '.' => number.push(c),
+            'e' | 'E' if !number.is_empty() => number.push(c),
+            '-' | '+' => {
+                if !(number.ends_with('e') || number.ends_with('E')) {
+                    flush(&mut number, &mut tokens)?;
+                }
+                number.push(c);
+            }
+            c if c.is_ascii_alphabetic() => {
+                flush(&mut number, &mut tokens)?;
+                tokens.push(PathToken::Command(c));
+            }
+            _ => flush(&mut number, &mut tokens)?,
+        }
+    }
+    flush(&mut number, &mut tokens)?;
+    Ok(tokens)
+}
+
+fn parse_points(value: &str) -> Result<Vec<Vec2>, SurfaceError> {
+    let numbers: Vec<f32> = tokenize(value)?
+        .into_iter()
+        .filter_map(|token| match token {
+            PathToken::Number(n) => Some(n),
+            PathToken::Command(_) => None,
+        })
+        .collect();
+    Ok(numbers.chunks_exact(2).map(|p| vec2(p[0], p[1])).collect())
+}
+
+fn parse_path(value: &str) -> Result<Vec<Vec<Vec2>>, SurfaceError> {
+    let mut polygons = Vec::new();
+    let mut current: Vec<Vec2> = Vec::new();
+    let mut pos = Vec2::ZERO;
+    let mut command = 'M';
+    let mut numbers = Vec::new();
+
+    let mut apply = |command: char, numbers: &[f32], current: &mut Vec<Vec2>, pos: &mut Vec2| {
+        // relative coordinates are offsets from the previous point
+        let relative = command.is_ascii_lowercase();
+        let origin = |pos: Vec2| if relative { pos } else { Vec2::ZERO };
+        match command.to_ascii_uppercase() {
+            'M' | 'L' => {
+                for (i, p) in numbers.chunks_exact(2).enumerate() {
+                    if i == 0 && command.to_ascii_uppercase() == 'M' {
+                        if current.len() > 1 {
+                            polygons.push(std::mem::take(current));
+                        }
+                        current.clear();
+                    }
+                    *pos = origin(*pos) + vec2(p[0], p[1]);
+                    current.push(*pos);
+                }
+            }
+            'H' => {
+                for &x in numbers {
+                    *pos = vec2(origin(*pos).x + x, pos.y);
+                    current.push(*pos);
+                }
+            }
+            'V' => {
+                for &y in numbers {
+                    *pos = vec2(pos.x, origin(*pos).y + y);
+                    current.push(*pos);
+                }
+            }
+            'Z' => {
+                if let Some(&first) = current.first() {
+                    *pos = first;
+                }
+                if current.len() > 1 {
+                    polygons.push(std::mem::take(current));
+                }
+            }
+            _ => {}
+        }
+    };
+
+    for token in tokenize(value)? {
+        match token {
+            PathToken::Number(n) => numbers.push(n),
+            PathToken::Command(c) => {
+                if !"MmLlHhVvZz".contains(c) {
+                    return Err(SurfaceError::UnsupportedCommand(c));
+                }
+                apply(command, &numbers, &mut current, &mut pos);
+                numbers.clear();
+                command = c;
+            }
+        }
+    }
+    apply(command, &numbers, &mut current, &mut pos);
+    if current.len() > 1 {
+        polygons.push(current);
+    }
+    Ok(polygons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color() -> Hsv {
+        hsv(0.3, 0.8, 0.7)
+    }
+
+    fn path(d: &str) -> Result<Surface, SurfaceError> {
+        Surface::from_svg(&format!("<svg><path d=\"{}\"/></svg>", d), color())
+    }
+
+    fn assert_close(a: &[Vec2], b: &[Vec2]) {
+        assert_eq!(a.len(), b.len(), "{:?} != {:?}", a, b);
+        for (p, q) in a.iter().zip(b) {
+            assert!(p.distance(*q) < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn absolute_path_is_normalized_to_one_unit() {
+        let surface = path("M 0 0 L 2 0 L 2 4 Z").unwrap();
+        // flipped to y-up, one unit tall, standing on the origin
+        assert_close(
+            &surface.polygons[0],
+            &[vec2(-0.25, 1.0), vec2(0.25, 1.0), vec2(0.25, 0.0)],
+        );
+        assert_eq!(surface.color, color());
+    }
+
+    #[test]
+    fn relative_and_line_commands_agree() {
+        let absolute = path("M10,10 H12 V14 H10 Z").unwrap();
+        for d in [
+            "m10,10 h2 v4 h-2 z",
+            "M10 10 L12 10 L12 14 L10 14 Z",
+            "M10 10 l2 0 l0 4 l-2 0 z",
+            "M10 10 12 10 12 14 10 14z",
+        ] {
+            assert_close(&path(d).unwrap().polygons[0], &absolute.polygons[0]);
+        }
+    }
+
+    #[test]
+    fn exponent_numbers() {
+        let plain = path("M0 0 L20 0 L20 40 z").unwrap();
+        let exponent = path("M0 0L2e1 0L2E+1 4e1z").unwrap();
+        assert_close(&exponent.polygons[0], &plain.polygons[0]);
+        let negative = path("M0 0L-2e1 0L-2e1-4e1z").unwrap();
+        assert_eq!(negative.polygons[0].len(), 3);
+    }
+
+    #[test]
+    fn attributes_after_any_whitespace() {
+        for svg in [
+            "<polygon\n  points=\"0,0 2,0 2,4\"/>",
+            "<polygon\tpoints='0,0 2,0 2,4'/>",
+            "<polyline id=\"a\" points = \"0,0 2,0 2,4\"/>",
+            "<path id=\"d\"\r\n d=\"M0 0 L2 0 L2 4 Z\"/>",
+        ] {
+            let surface = Surface::from_svg(svg, color()).unwrap();
+            assert_close(
+                &surface.polygons[0],
+                &[vec2(-0.25, 1.0), vec2(0.25, 1.0), vec2(0.25, 0.0)],
+            );
+        }
+    }
+
+    #[test]
+    fn several_shapes_share_one_normalization() {
+        let svg = "<svg><polygon points=\"0,0 1,0 1,1\"/><path d=\"M0 2 h1 v2 z\"/></svg>";
+        let surface = Surface::from_svg(svg, color()).unwrap();
+        assert_eq!(surface.polygons.len(), 2);
+        let all: Vec<Vec2> = surface.polygons.iter().flatten().copied().collect();
+        let min_y = all.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+        let max_y = all.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        assert!((min_y - 0.0).abs() < 1e-6 && (max_y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn curves_and_empty_documents_are_errors() {
+        assert_eq!(
+            path("M0 0 C1 1 2 2 3 3"),
+            Err(SurfaceError::UnsupportedCommand('C'))
+        );
+        assert_eq!(
+            path("M0 0 L1 1 a1 1 0 0 1 2 2").err(),
+            Some(SurfaceError::UnsupportedCommand('a'))
+        );
+        assert_eq!(
+            Surface::from_svg("<svg><rect/></svg>", color()),
+            Err(SurfaceError::NoShapes)
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Write;
+
+use nannou::prelude::*;
+
+use crate::{
+    color_palette::srgb_bytes,
+    geometry::Geometry,
+    stroke::{LineCap, LineJoin, StrokeStyle},
+};
+
+/// An SVG document with one `<polyline>` per colored run, polygons and points, and a
+/// viewBox fitted to the bounds including stroke widths. SVG's y axis points down, so
+/// coordinates are flipped.
+pub fn geometry_svg(geometry: &Geometry, stroke: &StrokeStyle) -> String {
+    let widest = geometry
+        .polylines
+        .iter()
+        .map(|polyline| polyline.width)
+        .fold(0.0, f32::max);
+    let glow = if stroke.glow.passes > 0 {
+        stroke.glow.width
+    } else {
+        0.0
+    };
+    let bounds = geometry
+        .bounds()
+        .unwrap_or(Rect::from_w_h(0.0, 0.0))
+        .pad(-(widest + glow) / 2.0);
+
+    let mut svg = String::new();
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w:.3}" height="{h:.3}" viewBox="{x:.3} {y:.3} {w:.3} {h:.3}">"#,
+        x = bounds.left(),
+        y = -bounds.top(),
+        w = bounds.w(),
+        h = bounds.h(),
+    )
+    .unwrap();
+
+    let mut attributes = format!(
+        r#"fill="none" stroke-linecap="{}" stroke-linejoin="{}""#,
+        match stroke.cap {
+            LineCap::Butt => "butt",
+            LineCap::Round => "round",
+            LineCap::Square => "square",
+        },
+        match stroke.join {
+            LineJoin::Miter => "miter",
+            LineJoin::Round => "round",
+            LineJoin::Bevel => "bevel",
+        },
+    );
+    if !stroke.dashes.is_empty() {
+        let dashes: Vec<String> = stroke.dashes.iter().map(|d| format!("{:.3}", d)).collect();
+        write!(
+            attributes,
+            r#" stroke-dasharray="{}" stroke-dashoffset="{:.3}""#,
+            dashes.join(" "),
+            stroke.dash_offset
+        )
+        .unwrap();
+    }
+    writeln!(svg, "<g {}>", attributes).unwrap();
+
+    let layers = stroke.glow.layers().into_iter().chain([(0.0, 1.0)]);
+    for (extra_width, alpha) in layers {
+        for polyline in &geometry.polylines {
+            for (color, points) in polyline.color_runs() {
+                write!(
+                    svg,
+                    r#"<polyline points="{}" stroke="{}" stroke-width="{:.3}""#,
+                    points_attribute(&points),
+                    hex(color),
+                    polyline.width + extra_width
+                )
+                .unwrap();
+                if alpha < 1.0 {
+                    write!(svg, r#" stroke-opacity="{:.3}""#, alpha).unwrap();
+                }
+                writeln!(svg, "/>").unwrap();
+            }
+        }
+    }
+    writeln!(svg, "</g>").unwrap();
+
+    for polygon in &geometry.polygons {
+        writeln!(
+            svg,
+            r#"<polygon points="{}" fill="{}"/>"#,
+            points_attribute(&polygon.points),
+            hex(polygon.color)
+        )
+        .unwrap();
+    }
+    for point in &geometry.points {
+        writeln!(
+            svg,
+            r#"<circle cx="{:.3}" cy="{:.3}" r="{:.3}" fill="{}"/>"#,
+            point.pos.x,
+            -point.pos.y,
+            point.radius,
+            hex(point.color)
+        )
+        .unwrap();
+    }
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+fn points_attribute(points: &[Vec2]) -> String {
+    let points: Vec<String> = points
+        .iter()
+        .map(|p| format!("{:.3},{:.3}", p.x, -p.y))
+        .collect();
+    points.join(" ")
+}
+
+fn hex(color: Hsv) -> String {
+    let [r, g, b] = srgb_bytes(color);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dragon_curve::DragonCurveLSystem, lsystems::SegmentMeta, stroke::Glow, DrawableLSystem,
+    };
+
+    fn meta() -> SegmentMeta {
+        SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index: 0,
+            arc_length: 0.0,
+            branch_id: 0,
+        }
+    }
+
+    fn count(svg: &str, element: &str) -> usize {
+        svg.matches(&format!("<{} ", element)).count()
+    }
+
+    #[test]
+    fn dragon_curve_document() {
+        let dragon = DragonCurveLSystem::new(vec2(0.0, 0.0), 0.0, 5.0, Hsv::new(240.0, 1.0, 1.0));
+        let svg = geometry_svg(&dragon.geometry(&0), dragon.stroke_style());
+        // up from the origin, then left: x -5..0 and y 0..5, padded by half the width of 2
+        // and flipped
+        assert!(svg.contains(r#"viewBox="-6.000 -6.000 7.000 7.000""#));
+        assert!(svg.contains(r#"width="7.000" height="7.000""#));
+        assert_eq!(count(&svg, "polyline"), 1);
+        assert!(svg.contains(
+            r##"<polyline points="0.000,-0.000 0.000,-5.000 -5.000,-5.000" stroke="#0000ff" stroke-width="2.000"/>"##
+        ));
+        assert!(svg.contains(r#"stroke-linecap="butt" stroke-linejoin="miter""#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn one_polyline_per_stroke_and_color() {
+        let (red, green) = (Hsv::new(0.0, 1.0, 1.0), Hsv::new(120.0, 1.0, 1.0));
+        let mut geometry = Geometry::new();
+        geometry.line(vec2(0.0, 0.0), vec2(1.0, 0.0), red, 1.0, meta());
+        geometry.line(vec2(1.0, 0.0), vec2(2.0, 0.0), red, 1.0, meta());
+        geometry.line(vec2(2.0, 0.0), vec2(3.0, 0.0), green, 1.0, meta());
+        geometry.line(vec2(0.0, 5.0), vec2(1.0, 5.0), green, 1.0, meta());
+        assert_eq!(geometry.polylines.len(), 2);
+
+        let svg = geometry_svg(&geometry, &StrokeStyle::default());
+        assert_eq!(count(&svg, "polyline"), 3);
+        assert_eq!(svg.matches(r##"stroke="#ff0000""##).count(), 1);
+        assert_eq!(svg.matches(r##"stroke="#00ff00""##).count(), 2);
+        assert!(svg.contains(r#"points="0.000,-0.000 1.000,-0.000 2.000,-0.000""#));
+    }
+
+    #[test]
+    fn glow_layers_go_underneath() {
+        let mut geometry = Geometry::new();
+        geometry.line(
+            vec2(0.0, 0.0),
+            vec2(10.0, 0.0),
+            Hsv::new(0.0, 1.0, 1.0),
+            2.0,
+            meta(),
+        );
+        let stroke = StrokeStyle {
+            glow: Glow::new(2, 8.0, 0.5),
+            ..StrokeStyle::default()
+        };
+        let svg = geometry_svg(&geometry, &stroke);
+        assert_eq!(count(&svg, "polyline"), 3);
+        let widths: Vec<&str> = svg
+            .split(r#"stroke-width=""#)
+            .skip(1)
+            .map(|rest| rest.split('"').next().unwrap())
+            .collect();
+        assert_eq!(widths, ["10.000", "6.000", "2.000"]);
+        assert_eq!(svg.matches(r#"stroke-opacity="0.250""#).count(), 2);
+        // the viewBox makes room for the widest glow
+        assert!(svg.contains(r#"viewBox="-5.000 -5.000 20.000 10.000""#));
+    }
+
+    #[test]
+    fn polygons_and_points_are_filled() {
+        let mut geometry = Geometry::new();
+        let blue = Hsv::new(240.0, 1.0, 1.0);
+        geometry.polygon(vec![vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)], blue);
+        geometry.point(vec2(2.0, 3.0), 0.5, blue);
+        let svg = geometry_svg(&geometry, &StrokeStyle::default());
+        assert!(svg.contains(
+            r##"<polygon points="0.000,-0.000 1.000,-0.000 0.000,-1.000" fill="#0000ff"/>"##
+        ));
+        assert!(svg.contains(r##"<circle cx="2.000" cy="-3.000" r="0.500" fill="#0000ff"/>"##));
+    }
+}
@@ -0,0 +1,192 @@
+use nannou::prelude::*;
+
+use crate::geometry::Geometry;
+
+/// Repeats a system's output around a center to build mandala-like compositions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Symmetry {
+    /// Rotational copies spread evenly around `center`, including the original.
+    pub copies: usize,
+    /// Adds a reflection of every copy.
+    pub mirror: bool,
+    /// Angle of the mirror axis through `center`, 0 is vertical.
+    pub mirror_angle: f32,
+    pub center: Vec2,
+    /// Hue shift between neighbouring copies, in turns.
+    pub hue_offset: f32,
+}
+
+impl Symmetry {
+    pub fn new(copies: usize, mirror: bool, center: Vec2, hue_offset: f32) -> Self {
+        Symmetry {
+            copies,
+            mirror,
+            mirror_angle: 0.0,
+            center,
+            hue_offset,
+        }
+    }
+    pub fn default() -> Self {
+        Symmetry::new(1, false, Vec2::ZERO, 0.0)
+    }
+    pub fn is_identity(&self) -> bool {
+        self.copies <= 1 && !self.mirror
+    }
+    pub fn apply(&self, geometry: &mut Geometry) {
+        if self.is_identity() {
+            return;
+        }
+        let original = std::mem::take(geometry);
+        let copies = self.copies.max(1);
+        let axis = vec2(0.0, 1.0).rotate(self.mirror_angle);
+
+        geometry.diagnostics = original.diagnostics.clone();
+        for i in 0..copies {
+            let angle = TAU * i as f32 / copies as f32;
+            let hue_shift = self.hue_offset * i as f32 * 360.0;
+            let rotate = |p: Vec2| self.center + (p - self.center).rotate(angle);
+
+            let mut copy = original.clone();
+            copy.map_points(rotate);
+            copy.map_colors(|color| shift_hue(color, hue_shift));
+            geometry.append(copy);
+
+            if self.mirror {
+                let mut reflection = original.clone();
+                reflection.map_points(|p| {
+                    let offset = p - self.center;
+                    rotate(self.center + axis * 2.0 * offset.dot(axis) - offset)
+                });
+                reflection.map_colors(|color| shift_hue(color, hue_shift));
+                geometry.append(reflection);
+            }
+        }
+    }
+}
+
+fn shift_hue(color: Hsv, degrees: f32) -> Hsv {
+    Hsv::new(
+        color.hue.to_degrees() + degrees,
+        color.saturation,
+        color.value,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentMeta;
+
+    fn center() -> Vec2 {
+        vec2(5.0, 5.0)
+    }
+
+    /// A segment pointing away from the center, a little to the right of straight up.
+    fn spoke(hue: f32) -> Geometry {
+        let meta = SegmentMeta {
+            depth: 0,
+            generation: 0,
+            index: 0,
+            arc_length: 0.0,
+            branch_id: 0,
+        };
+        let mut geometry = Geometry::new();
+        let color = Hsv::new(hue, 1.0, 1.0);
+        geometry.line(
+            center() + vec2(1.0, 1.0),
+            center() + vec2(1.0, 2.0),
+            color,
+            1.0,
+            meta,
+        );
+        geometry
+    }
+
+    fn assert_points(geometry: &Geometry, expected: &[[Vec2; 2]]) {
+        let actual: Vec<&[Vec2]> = geometry
+            .polylines
+            .iter()
+            .map(|polyline| polyline.points.as_slice())
+            .collect();
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (points, expected) in actual.iter().zip(expected) {
+            for (p, q) in points.iter().zip(expected) {
+                assert!(
+                    p.distance(center() + *q) < 1e-5,
+                    "{:?} != {:?}",
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn copies_rotate_about_the_center() {
+        let mut geometry = spoke(0.0);
+        Symmetry::new(4, false, center(), 0.0).apply(&mut geometry);
+        assert_points(
+            &geometry,
+            &[
+                [vec2(1.0, 1.0), vec2(1.0, 2.0)],
+                [vec2(-1.0, 1.0), vec2(-2.0, 1.0)],
+                [vec2(-1.0, -1.0), vec2(-1.0, -2.0)],
+                [vec2(1.0, -1.0), vec2(2.0, -1.0)],
+            ],
+        );
+    }
+
+    #[test]
+    fn mirror_reflects_across_its_axis() {
+        let mut vertical = spoke(0.0);
+        Symmetry::new(1, true, center(), 0.0).apply(&mut vertical);
+        assert_points(
+            &vertical,
+            &[
+                [vec2(1.0, 1.0), vec2(1.0, 2.0)],
+                [vec2(-1.0, 1.0), vec2(-1.0, 2.0)],
+            ],
+        );
+
+        let mut horizontal = spoke(0.0);
+        let symmetry = Symmetry {
+            mirror_angle: PI / 2.0,
+            ..Symmetry::new(1, true, center(), 0.0)
+        };
+        symmetry.apply(&mut horizontal);
+        assert_points(
+            &horizontal,
+            &[
+                [vec2(1.0, 1.0), vec2(1.0, 2.0)],
+                [vec2(1.0, -1.0), vec2(1.0, -2.0)],
+            ],
+        );
+    }
+
+    #[test]
+    fn hue_shifts_by_turns_per_copy() {
+        let mut geometry = spoke(10.0);
+        Symmetry::new(3, true, center(), 0.25).apply(&mut geometry);
+        let hues: Vec<f32> = geometry
+            .polylines
+            .iter()
+            .map(|polyline| polyline.colors[0].hue.to_positive_degrees())
+            .collect();
+        let expected = [10.0, 10.0, 100.0, 100.0, 190.0, 190.0];
+        for (hue, expected) in hues.iter().zip(expected) {
+            assert!((hue - expected).abs() < 1e-3, "{:?}", hues);
+        }
+    }
+
+    #[test]
+    fn identity_leaves_the_geometry_alone() {
+        for symmetry in [Symmetry::default(), Symmetry::new(0, false, center(), 0.5)] {
+            assert!(symmetry.is_identity());
+            let mut geometry = spoke(10.0);
+            symmetry.apply(&mut geometry);
+            assert_eq!(geometry, spoke(10.0));
+        }
+        assert!(!Symmetry::new(1, true, center(), 0.0).is_identity());
+        assert!(!Symmetry::new(2, false, center(), 0.0).is_identity());
+    }
+}